@@ -1,27 +1,34 @@
+use duty::process::ProcessPool;
 use duty::transport::Bincode;
-use readwrite::ReadWrite;
 use std::error::Error;
-use std::process::{Command, Stdio};
 
-mod ttv_calc;
+pub mod ttv_calc;
 use ttv_calc::TtvCalcClient;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut child = Command::new("local_worker")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()?;
+    let workers = ProcessPool::new("local_worker").spawn(Bincode::new)?;
+    let chunk = 42 / workers.len() as u64 + 1;
 
-    let stream = ReadWrite::new(
-        child.stdout.take().expect("Missing stdout"),
-        child.stdin.take().expect("Missing stdin"),
-    );
+    let results = std::thread::scope(|s| {
+        let handles: Vec<_> = workers
+            .into_iter()
+            .enumerate()
+            .map(|(i, worker)| {
+                s.spawn(move || -> Result<Vec<f64>, duty::Error> {
+                    let from = (i as u64 * chunk).min(42);
+                    let to = (from + chunk).min(42);
+                    TtvCalcClient::new(worker)?.ttv_calc(from, to)
+                })
+            })
+            .collect();
 
-    let client = TtvCalcClient::new(Bincode::new(stream))?;
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("Thread panicked"))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
 
-    let sum = client.ttv_calc(0, 42)?;
-
-    println!("{:?}", sum);
+    println!("{:?}", results.concat());
 
     Ok(())
 }
@@ -9,10 +9,10 @@ mod ttv_calculator;
 use ttv_calculator::Calculator;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut transport = Bincode::new(Stdinout::new());
+    let transport = Bincode::new(Stdinout::new());
 
     let calculator = Calculator { factor: 1.5 };
-    calculator.handle_next_request(&mut transport)?;
+    calculator.serve(transport)?;
 
     Ok(())
 }
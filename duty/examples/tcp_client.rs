@@ -2,7 +2,7 @@ use duty::transport::Bincode;
 use std::error::Error;
 use std::net::TcpStream;
 
-mod ttv_calc;
+pub mod ttv_calc;
 use ttv_calc::TtvCalcClient;
 
 fn main() -> Result<(), Box<dyn Error>> {
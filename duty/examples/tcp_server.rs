@@ -12,12 +12,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     let listener = TcpListener::bind("0.0.0.0")?;
 
     let calculator = Calculator { factor: 1.5 };
-
-    for connection in listener.incoming() {
-        let mut transport = Bincode::new(connection?);
-
-        while let Ok(()) = calculator.handle_next_request(&mut transport) {}
-    }
+    calculator.serve_listener(&listener, Bincode::new)?;
 
     Ok(())
 }
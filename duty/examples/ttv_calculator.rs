@@ -1,5 +1,6 @@
 use crate::ttv_calc::TtvCalc;
 
+#[derive(Clone)]
 pub struct Calculator {
     pub factor: f64,
 }
@@ -0,0 +1,138 @@
+use crate::error::Error;
+use crate::transport::Transport;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+const CHALLENGE_LEN: usize = 32;
+
+/// Handshake run once per connection, before any request is sent.
+///
+/// Client side calls [`Authenticator::authenticate`], server side calls
+/// [`Authenticator::verify`] with the same kind of authenticator. Both fail
+/// with [`Error::Unauthenticated`] when the peer is rejected.
+pub trait Authenticator {
+    fn authenticate<T: Transport>(&self, transport: &mut T) -> Result<(), Error>;
+
+    fn verify<T: Transport>(&self, transport: &mut T) -> Result<(), Error>;
+}
+
+/// Accepts every peer without any handshake, used by
+/// [`ServerBuilder`](crate::listener::ServerBuilder) unless it is given
+/// another authenticator.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoAuth;
+
+impl Authenticator for NoAuth {
+    fn authenticate<T: Transport>(&self, _transport: &mut T) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn verify<T: Transport>(&self, _transport: &mut T) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum AuthMessage {
+    Token(String),
+    Challenge(Vec<u8>),
+    Response(Vec<u8>),
+    Accepted,
+    Rejected,
+}
+
+/// Client sends pre-shared token which server compares with its own.
+pub struct TokenAuth {
+    token: String,
+}
+
+impl TokenAuth {
+    pub fn new(token: impl Into<String>) -> TokenAuth {
+        TokenAuth {
+            token: token.into(),
+        }
+    }
+}
+
+impl Authenticator for TokenAuth {
+    fn authenticate<T: Transport>(&self, transport: &mut T) -> Result<(), Error> {
+        transport.send(&AuthMessage::Token(self.token.clone()))?;
+        expect_accepted(transport)
+    }
+
+    fn verify<T: Transport>(&self, transport: &mut T) -> Result<(), Error> {
+        let accepted = match transport.receive()? {
+            AuthMessage::Token(token) => constant_time_eq(token.as_bytes(), self.token.as_bytes()),
+            _ => false,
+        };
+        respond(transport, accepted)
+    }
+}
+
+/// Challenge-response with HMAC-SHA256 of a random challenge keyed with
+/// pre-shared secret. The secret never leaves either side.
+pub struct HmacAuth {
+    secret: Vec<u8>,
+}
+
+impl HmacAuth {
+    pub fn new(secret: impl Into<Vec<u8>>) -> HmacAuth {
+        HmacAuth {
+            secret: secret.into(),
+        }
+    }
+
+    fn mac(&self, challenge: &[u8]) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        mac.update(challenge);
+        mac
+    }
+}
+
+impl Authenticator for HmacAuth {
+    fn authenticate<T: Transport>(&self, transport: &mut T) -> Result<(), Error> {
+        let challenge = match transport.receive()? {
+            AuthMessage::Challenge(challenge) => challenge,
+            _ => return Err(Error::Unauthenticated),
+        };
+
+        let response = self.mac(&challenge).finalize().into_bytes().to_vec();
+        transport.send(&AuthMessage::Response(response))?;
+        expect_accepted(transport)
+    }
+
+    fn verify<T: Transport>(&self, transport: &mut T) -> Result<(), Error> {
+        let mut challenge = vec![0; CHALLENGE_LEN];
+        getrandom::getrandom(&mut challenge).map_err(std::io::Error::other)?;
+        transport.send(&AuthMessage::Challenge(challenge.clone()))?;
+
+        let accepted = match transport.receive()? {
+            AuthMessage::Response(response) => self.mac(&challenge).verify_slice(&response).is_ok(),
+            _ => false,
+        };
+        respond(transport, accepted)
+    }
+}
+
+fn expect_accepted<T: Transport>(transport: &mut T) -> Result<(), Error> {
+    match transport.receive()? {
+        AuthMessage::Accepted => Ok(()),
+        _ => Err(Error::Unauthenticated),
+    }
+}
+
+fn respond<T: Transport>(transport: &mut T, accepted: bool) -> Result<(), Error> {
+    if accepted {
+        transport.send(&AuthMessage::Accepted)
+    } else {
+        tracing::warn!("rejected unauthenticated peer");
+        transport.send(&AuthMessage::Rejected)?;
+        Err(Error::Unauthenticated)
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
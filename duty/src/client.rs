@@ -1,35 +1,84 @@
-use crate::error::Error;
+use crate::auth::Authenticator;
+use crate::envelope::{Envelope, Header};
+use crate::error::{CallContext, Error};
+use crate::metrics::{Recorder, Side};
 use crate::procedure::Procedure;
+use crate::trace;
 use crate::transport::Transport;
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
 
+/// Ends the session of a client generated by the
+/// [`service`](crate::service) macro, so that the service sees the client
+/// disconnecting cleanly. Called as `Close::close(client)`, so that it
+/// doesn't collide with service methods named `close`.
+pub trait Close {
+    fn close(client: Self) -> Result<(), Error>;
+}
+
 pub struct Client<T> {
     transport: Arc<Mutex<T>>,
+    next_request_id: u64,
 }
 
 impl<T: Transport> Client<T> {
     pub fn new(transport: T) -> Client<T> {
         Client {
             transport: Arc::new(Mutex::new(transport)),
+            next_request_id: 0,
         }
     }
 
+    /// Authenticates with `authenticator` before any call is made.
+    pub fn with_auth<A: Authenticator>(
+        mut transport: T,
+        authenticator: &A,
+    ) -> Result<Client<T>, Error> {
+        authenticator.authenticate(&mut transport)?;
+        Ok(Client::new(transport))
+    }
+
     pub fn call<P: Procedure>(&mut self, proc: P) -> CallHandle<P::Response> {
         let request: P::Request = proc.into();
 
+        // Named like calls of generated clients, which use the trait and
+        // method identifiers
+        let header = Header::new(
+            self.next_request_id,
+            short_type_name::<P::Request>(),
+            short_type_name::<P>(),
+        );
+        self.next_request_id += 1;
+
+        let span = trace::client_span(&header);
+        let envelope = Envelope::new(header, request);
+
         let transport = self.transport.clone();
         let join_handle = std::thread::spawn(move || {
-            transport
-                .lock()
-                .expect("Mutex is poisoned")
-                .send_receive(&request)
+            let _span_guard = span.enter();
+            let mut transport = transport.lock().expect("Mutex is poisoned");
+            let recorder = Recorder::start(Side::Client, &envelope.header, transport.byte_count());
+            let header = envelope.header.clone();
+            let result = transport.call_owned(envelope);
+            recorder.finish(&*transport, &result);
+            result.map_err(|e| {
+                e.with_context(|| {
+                    CallContext::new(&header.service, &header.method, transport.peer_addr())
+                })
+            })
         });
 
         CallHandle { join_handle }
     }
 }
 
+/// Name of the type without its module path and generic arguments.
+fn short_type_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
 pub struct CallHandle<R> {
     join_handle: JoinHandle<Result<R, Error>>,
 }
@@ -49,4 +98,13 @@ impl<R> CallHandle<R> {
 }
 
 #[cfg(test)]
-mod test {}
+mod test {
+    use super::*;
+
+    #[test]
+    fn short_names() {
+        assert_eq!(short_type_name::<Client<u8>>(), "Client");
+        assert_eq!(short_type_name::<Vec<std::string::String>>(), "Vec");
+        assert_eq!(short_type_name::<u32>(), "u32");
+    }
+}
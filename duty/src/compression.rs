@@ -0,0 +1,266 @@
+use crate::transport::PeerAddr;
+use std::io::{self, Read, Write};
+
+/// Messages smaller than this are sent uncompressed by default.
+pub const DEFAULT_THRESHOLD: usize = 512;
+
+/// Frames larger than this after decompression are rejected by default.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+const UNCOMPRESSED: u8 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    #[cfg(feature = "lz4")]
+    Lz4,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Algorithm {
+    /// All algorithms compiled in, most preferred first.
+    pub const ALL: &'static [Algorithm] = &[
+        #[cfg(feature = "zstd")]
+        Algorithm::Zstd,
+        #[cfg(feature = "lz4")]
+        Algorithm::Lz4,
+    ];
+
+    fn id(&self) -> u8 {
+        match *self {
+            #[cfg(feature = "lz4")]
+            Algorithm::Lz4 => 1,
+            #[cfg(feature = "zstd")]
+            Algorithm::Zstd => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Algorithm> {
+        Algorithm::ALL.iter().copied().find(|a| a.id() == id)
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match *self {
+            #[cfg(feature = "lz4")]
+            Algorithm::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+            #[cfg(feature = "zstd")]
+            Algorithm::Zstd => zstd::bulk::compress(data, 0),
+        }
+    }
+
+    /// Fails instead of producing more than `max_size` bytes, so that small
+    /// frames can't claim huge sizes or expand to them.
+    fn decompress(&self, data: &[u8], max_size: usize) -> io::Result<Vec<u8>> {
+        match *self {
+            #[cfg(feature = "lz4")]
+            Algorithm::Lz4 => {
+                let (size, data) =
+                    lz4_flex::block::uncompressed_size(data).map_err(invalid_data)?;
+                if size > max_size {
+                    return Err(too_large(size, max_size));
+                }
+                lz4_flex::block::decompress(data, size).map_err(invalid_data)
+            }
+            #[cfg(feature = "zstd")]
+            Algorithm::Zstd => {
+                let mut decompressed = Vec::new();
+                zstd::stream::Decoder::with_buffer(data)?
+                    .take(max_size as u64 + 1)
+                    .read_to_end(&mut decompressed)
+                    .map_err(invalid_data)?;
+                if decompressed.len() > max_size {
+                    return Err(too_large(decompressed.len(), max_size));
+                }
+                Ok(decompressed)
+            }
+        }
+    }
+}
+
+/// Stream compressing data written to the wrapped stream, to be used under
+/// any [`Framed`](crate::transport::Framed) transport.
+///
+/// Data written between flushes, i.e. a message, is sent as one frame:
+/// compressed if it is larger than the threshold and prefixed with the
+/// algorithm id and its length. Both sides must wrap their streams, during
+/// [`Compressed::negotiate`] they agree on the algorithm supported by both of
+/// them. If there is none, frames are sent uncompressed. Frames larger than
+/// the maximum size, before or after decompression, fail reading with
+/// [`io::ErrorKind::InvalidData`], which transports report as
+/// [`Error::Decode`](crate::error::Error::Decode).
+///
+/// ```no_run
+/// use duty::compression::Compressed;
+/// use duty::transport::Json;
+/// use std::net::TcpStream;
+///
+/// let stream = TcpStream::connect("127.0.0.1:4000")?;
+/// let transport = Json::new(Compressed::negotiate(stream)?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct Compressed<S> {
+    stream: S,
+    algorithm: Option<Algorithm>,
+    threshold: usize,
+    max_frame_size: usize,
+    /// Decompressed frame being read.
+    incoming: Vec<u8>,
+    position: usize,
+    /// Data to be sent as a frame on flush.
+    outgoing: Vec<u8>,
+}
+
+impl<S: Read + Write> Compressed<S> {
+    pub fn negotiate(stream: S) -> io::Result<Compressed<S>> {
+        Compressed::negotiate_with(stream, Algorithm::ALL, DEFAULT_THRESHOLD)
+    }
+
+    /// Like [`Compressed::negotiate`], but offers only `algorithms` to the
+    /// peer and compresses messages of at least `threshold` bytes.
+    pub fn negotiate_with(
+        mut stream: S,
+        algorithms: &[Algorithm],
+        threshold: usize,
+    ) -> io::Result<Compressed<S>> {
+        let mut hello = vec![algorithms.len() as u8];
+        hello.extend(algorithms.iter().map(Algorithm::id));
+        stream.write_all(&hello)?;
+        stream.flush()?;
+
+        let mut count = [0];
+        stream.read_exact(&mut count)?;
+        let mut peer = vec![0; count[0] as usize];
+        stream.read_exact(&mut peer)?;
+
+        // Both sides pick the same algorithm, regardless of the order in
+        // which they listed them
+        let algorithm = Algorithm::ALL
+            .iter()
+            .copied()
+            .find(|a| algorithms.contains(a) && peer.contains(&a.id()));
+
+        tracing::debug!("negotiated compression: {:?}", algorithm);
+
+        Ok(Compressed {
+            stream,
+            algorithm,
+            threshold,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            incoming: Vec::new(),
+            position: 0,
+            outgoing: Vec::new(),
+        })
+    }
+
+    /// Rejects incoming frames larger than `size` bytes instead of
+    /// [`DEFAULT_MAX_FRAME_SIZE`].
+    pub fn max_frame_size(mut self, size: usize) -> Compressed<S> {
+        self.max_frame_size = size;
+        self
+    }
+
+    /// Algorithm used for outgoing messages.
+    pub fn algorithm(&self) -> Option<Algorithm> {
+        self.algorithm
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Reads the next frame. Returns false at the end of the stream.
+    fn read_frame(&mut self) -> io::Result<bool> {
+        let mut flags = [0];
+        if self.stream.read(&mut flags)? == 0 {
+            return Ok(false);
+        }
+
+        let mut len = [0; 4];
+        self.stream.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > self.max_frame_size {
+            return Err(too_large(len, self.max_frame_size));
+        }
+
+        let mut payload = vec![0; len];
+        self.stream.read_exact(&mut payload)?;
+
+        self.incoming = match flags[0] {
+            UNCOMPRESSED => payload,
+            id => Algorithm::from_id(id)
+                .ok_or_else(|| invalid_data(format!("unsupported compression algorithm {}", id)))?
+                .decompress(&payload, self.max_frame_size)?,
+        };
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl<S: Read + Write> Read for Compressed<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.incoming.len() {
+            if !self.read_frame()? {
+                return Ok(0);
+            }
+        }
+
+        let len = buf.len().min(self.incoming.len() - self.position);
+        buf[..len].copy_from_slice(&self.incoming[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl<S: Read + Write> Write for Compressed<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.outgoing.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.outgoing.is_empty() {
+            let compressed = match self.algorithm {
+                Some(algorithm) if self.outgoing.len() >= self.threshold => {
+                    Some((algorithm.id(), algorithm.compress(&self.outgoing)?))
+                }
+                _ => None,
+            };
+
+            // Incompressible data is sent as is
+            let (flags, payload) = match &compressed {
+                Some((id, payload)) if payload.len() < self.outgoing.len() => (*id, payload),
+                _ => (UNCOMPRESSED, &self.outgoing),
+            };
+
+            let len = u32::try_from(payload.len()).map_err(invalid_data)?;
+            let mut frame = Vec::with_capacity(5 + payload.len());
+            frame.push(flags);
+            frame.extend_from_slice(&len.to_le_bytes());
+            frame.extend_from_slice(payload);
+            self.stream.write_all(&frame)?;
+            self.outgoing.clear();
+        }
+
+        self.stream.flush()
+    }
+}
+
+impl<S: PeerAddr> PeerAddr for Compressed<S> {
+    fn peer_addr(&self) -> Option<String> {
+        self.stream.peer_addr()
+    }
+}
+
+fn invalid_data<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn too_large(size: usize, max_size: usize) -> io::Error {
+    invalid_data(format!(
+        "frame of {} bytes exceeds maximum of {} bytes",
+        size, max_size
+    ))
+}
@@ -1,6 +1,7 @@
 use crate::client::{CallHandle, Client};
 use crate::error::Error;
 use crate::procedure::Procedure;
+use crate::trace::TraceContext;
 use crate::transport::Transport;
 
 pub struct Dispatcher<T: Transport> {
@@ -15,6 +16,9 @@ impl<T: Transport> Dispatcher<T> {
     }
 
     pub fn call<P: Procedure>(&mut self, proc: &P) -> DispatchHandle<P> {
+        // All calls of a single dispatch belong to the same trace
+        let _context_guard = TraceContext::new_child().enter();
+
         let call_handlers = self
             .clients
             .iter_mut()
@@ -0,0 +1,67 @@
+use crate::trace::TraceContext;
+use serde::{Deserialize, Serialize};
+
+/// Metadata sent along with every request.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Header {
+    pub request_id: u64,
+    pub service: String,
+    pub method: String,
+    pub trace: TraceContext,
+    /// Repeating the call doesn't change its outcome, so it may be sent
+    /// again after the connection was lost, see
+    /// [`Reconnect`](crate::reconnect::Reconnect). Known only to the
+    /// client side, it isn't sent.
+    #[serde(skip)]
+    pub idempotent: bool,
+}
+
+impl Header {
+    /// Creates header for a new call. Trace context is inherited from the
+    /// current thread if we are already inside a traced call.
+    pub fn new(request_id: u64, service: &str, method: &str) -> Header {
+        Header {
+            request_id,
+            service: service.to_owned(),
+            method: method.to_owned(),
+            trace: TraceContext::new_child(),
+            idempotent: false,
+        }
+    }
+
+    /// Marks the call as idempotent.
+    pub fn idempotent(mut self) -> Header {
+        self.idempotent = true;
+        self
+    }
+}
+
+/// Request enum generated by the [`service`](crate::service) macro, which
+/// lets transports resolve methods named on the wire.
+pub trait ServiceRequest {
+    /// Service name sent in the header.
+    const SERVICE: &'static str;
+
+    /// Method names sent in the header.
+    const METHODS: &'static [&'static str];
+
+    /// Variant names seen by self-describing formats. Generic methods have
+    /// one for each instance, e.g. `sum<f64>`.
+    const VARIANTS: &'static [&'static str];
+
+    /// Method name of this request, as sent in the header.
+    fn method(&self) -> &'static str;
+}
+
+/// Request together with its header, as it is sent over the transport.
+#[derive(Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub header: Header,
+    pub body: T,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(header: Header, body: T) -> Envelope<T> {
+        Envelope { header, body }
+    }
+}
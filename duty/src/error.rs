@@ -1,7 +1,182 @@
+use std::fmt;
+use std::io;
+
+/// Error of any type, kept as the source of [`Error::Decode`] and
+/// [`Error::Encode`].
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("message deserialization failed: {0}")]
-    MsgDeserFailed(String),
-    #[error("message serialization {0}")]
-    MsgSerFailed(String),
+    /// Received message is malformed or of unexpected type.
+    #[error("message decoding failed: {0}")]
+    Decode(#[source] BoxError),
+    /// Message can't be represented in the format of the transport.
+    #[error("message encoding failed: {0}")]
+    Encode(#[source] BoxError),
+    #[error("remote error {code}: {message}")]
+    Remote { code: i64, message: String },
+    /// HTTP response with status other than 2xx.
+    #[error("HTTP status {status}: {body}")]
+    Http { status: u16, body: String },
+    #[error("authentication failed")]
+    Unauthenticated,
+    /// Peer closed the connection between messages, which ends serving it
+    /// normally.
+    #[error("connection closed")]
+    ConnectionClosed,
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// Failure of a call made by a client, with the call it happened in.
+    #[error("{context}: {source}")]
+    Call {
+        context: CallContext,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Adds context of the call the error happened in, unless it already
+    /// has one.
+    pub fn with_context<F: FnOnce() -> CallContext>(self, context: F) -> Error {
+        match self {
+            Error::Call { .. } => self,
+            source => Error::Call {
+                context: context(),
+                source: Box::new(source),
+            },
+        }
+    }
+
+    /// Context of the call, if the error happened in one.
+    pub fn context(&self) -> Option<&CallContext> {
+        match self {
+            Error::Call { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// The error without call context, for matching on its kind.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Call { source, .. } => source.root(),
+            error => error,
+        }
+    }
+
+    /// Whether repeating the call, possibly over a new connection, may
+    /// succeed. True for lost connections, timeouts and remote errors
+    /// signalling temporary unavailability, false for errors which would
+    /// happen again, like malformed messages or rejected credentials.
+    pub fn is_retryable(&self) -> bool {
+        match self.root() {
+            Error::ConnectionClosed => true,
+            Error::Io(e) => matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::NotConnected
+                    | io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::TimedOut
+                    | io::ErrorKind::Interrupted
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::UnexpectedEof
+            ),
+            // Statuses of overloaded or restarting servers
+            Error::Http { status, .. } => matches!(status, 429 | 502 | 503 | 504),
+            _ => false,
+        }
+    }
+
+    pub fn decode<E: Into<BoxError>>(error: E) -> Error {
+        Error::Decode(error.into())
+    }
+
+    pub fn encode<E: Into<BoxError>>(error: E) -> Error {
+        Error::Encode(error.into())
+    }
+
+    /// Keeps I/O errors of the underlying stream as [`Error::Io`].
+    pub(crate) fn from_bincode(error: bincode::Error, map: fn(BoxError) -> Error) -> Error {
+        match *error {
+            bincode::ErrorKind::Io(e) => Error::Io(e),
+            _ => map(error),
+        }
+    }
+
+    /// Keeps I/O errors of the underlying stream as [`Error::Io`].
+    pub(crate) fn from_json(error: serde_json::Error, map: fn(BoxError) -> Error) -> Error {
+        if error.is_io() {
+            Error::Io(error.into())
+        } else {
+            map(error.into())
+        }
+    }
+
+    /// Keeps I/O errors of the underlying stream as [`Error::Io`].
+    #[cfg(feature = "msgpack")]
+    pub(crate) fn from_msgpack_decode(error: rmp_serde::decode::Error) -> Error {
+        match error {
+            rmp_serde::decode::Error::InvalidMarkerRead(e)
+            | rmp_serde::decode::Error::InvalidDataRead(e) => Error::Io(e),
+            error => Error::Decode(error.into()),
+        }
+    }
+
+    /// Keeps I/O errors of the underlying stream as [`Error::Io`].
+    #[cfg(feature = "msgpack")]
+    pub(crate) fn from_msgpack_encode(error: rmp_serde::encode::Error) -> Error {
+        match error {
+            rmp_serde::encode::Error::InvalidValueWrite(e) => Error::Io(e.into()),
+            error => Error::Encode(error.into()),
+        }
+    }
+
+    /// Keeps I/O errors of the underlying stream as [`Error::Io`].
+    #[cfg(feature = "cbor")]
+    pub(crate) fn from_cbor_decode(error: ciborium::de::Error<io::Error>) -> Error {
+        match error {
+            ciborium::de::Error::Io(e) => Error::Io(e),
+            error => Error::Decode(error.into()),
+        }
+    }
+
+    /// Keeps I/O errors of the underlying stream as [`Error::Io`].
+    #[cfg(feature = "cbor")]
+    pub(crate) fn from_cbor_encode(error: ciborium::ser::Error<io::Error>) -> Error {
+        match error {
+            ciborium::ser::Error::Io(e) => Error::Io(e),
+            error => Error::Encode(error.into()),
+        }
+    }
+}
+
+/// Call an error happened in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallContext {
+    pub service: String,
+    pub method: String,
+    /// Address of the remote side, if known by the transport.
+    pub peer_addr: Option<String>,
+}
+
+impl CallContext {
+    pub fn new(service: &str, method: &str, peer_addr: Option<&str>) -> CallContext {
+        CallContext {
+            service: service.to_owned(),
+            method: method.to_owned(),
+            peer_addr: peer_addr.map(str::to_owned),
+        }
+    }
+}
+
+impl fmt::Display for CallContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "call to {}::{}", self.service, self.method)?;
+        if let Some(peer_addr) = &self.peer_addr {
+            write!(f, " at {}", peer_addr)?;
+        }
+        Ok(())
+    }
 }
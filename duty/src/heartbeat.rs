@@ -0,0 +1,306 @@
+use crate::transport::PeerAddr;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Period of silence after which the peer is pinged by default.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of unanswered pings after which the peer is considered dead by
+/// default.
+pub const DEFAULT_MISSED: u32 = 3;
+
+/// Data frames longer than this are rejected, so that a corrupted or
+/// malicious length can't make the reader allocate arbitrary amounts of
+/// memory.
+pub const MAX_FRAME_SIZE: usize = 64 * 1024 * 1024;
+
+const DATA: u8 = 0;
+const PING: u8 = 1;
+const PONG: u8 = 2;
+
+/// Stream which can be read and written from different threads.
+pub trait Duplex: Read + Write + Send + Sized + 'static {
+    fn try_clone(&self) -> io::Result<Self>;
+
+    /// Closes both directions, making pending reads of the clones return.
+    fn shutdown(&self) -> io::Result<()>;
+}
+
+impl Duplex for TcpStream {
+    fn try_clone(&self) -> io::Result<TcpStream> {
+        TcpStream::try_clone(self)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+#[cfg(unix)]
+impl Duplex for std::os::unix::net::UnixStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        std::os::unix::net::UnixStream::try_clone(self)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        std::os::unix::net::UnixStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+/// Stream detecting peers which went away without closing the connection,
+/// e.g. because their host or network died.
+///
+/// Data is sent in frames, mixed with ping and pong control frames. While
+/// reading, the peer is pinged whenever it is silent for the interval and
+/// declared dead once it misses the given number of pings in a row. Reading
+/// and writing then fail with [`io::ErrorKind::TimedOut`], which fails
+/// pending calls and ends serving the connection. Pings are answered by a
+/// background thread, so idle peers stay alive. Both sides must wrap their
+/// streams.
+///
+/// ```no_run
+/// use duty::heartbeat::Heartbeat;
+/// use duty::transport::Bincode;
+/// use std::net::TcpStream;
+/// use std::time::Duration;
+///
+/// let stream = TcpStream::connect("127.0.0.1:4000")?;
+/// let transport = Bincode::new(Heartbeat::with_interval(stream, Duration::from_secs(1), 3)?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct Heartbeat<S: Duplex> {
+    writer: Arc<Mutex<S>>,
+    frames: Receiver<io::Result<Vec<u8>>>,
+    /// Number of frames of any kind received so far.
+    received: Arc<AtomicU64>,
+    interval: Duration,
+    max_missed: u32,
+    /// Data frame being read.
+    data: Vec<u8>,
+    position: usize,
+    /// Data to be sent as a frame on flush.
+    outgoing: Vec<u8>,
+    closed: bool,
+    dead: bool,
+}
+
+impl<S: Duplex> Heartbeat<S> {
+    pub fn new(stream: S) -> io::Result<Heartbeat<S>> {
+        Heartbeat::with_interval(stream, DEFAULT_INTERVAL, DEFAULT_MISSED)
+    }
+
+    /// Like [`Heartbeat::new`], but pings after `interval` of silence and
+    /// gives up after `max_missed` unanswered pings.
+    pub fn with_interval(
+        stream: S,
+        interval: Duration,
+        max_missed: u32,
+    ) -> io::Result<Heartbeat<S>> {
+        let reader = stream.try_clone()?;
+        let writer = Arc::new(Mutex::new(stream));
+        let received = Arc::new(AtomicU64::new(0));
+        let (sender, frames) = mpsc::channel();
+
+        std::thread::spawn({
+            let writer = writer.clone();
+            let received = received.clone();
+            move || read_frames(reader, &writer, &received, &sender)
+        });
+
+        Ok(Heartbeat {
+            writer,
+            frames,
+            received,
+            interval,
+            max_missed,
+            data: Vec::new(),
+            position: 0,
+            outgoing: Vec::new(),
+            closed: false,
+            dead: false,
+        })
+    }
+
+    /// Waits for the next data frame, pinging the peer while it is silent.
+    /// Returns false at the end of the stream.
+    fn next_frame(&mut self) -> io::Result<bool> {
+        let mut missed = 0;
+        let mut received = self.received.load(Ordering::SeqCst);
+
+        loop {
+            if self.dead {
+                return Err(dead_error());
+            }
+
+            match self.frames.recv_timeout(self.interval) {
+                Ok(Ok(data)) => {
+                    self.data = data;
+                    self.position = 0;
+                    return Ok(true);
+                }
+                Ok(Err(e)) => {
+                    self.closed = true;
+                    return Err(e);
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.closed = true;
+                    return Ok(false);
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    let now_received = self.received.load(Ordering::SeqCst);
+                    if now_received != received {
+                        // Peer answered, though not with data yet
+                        received = now_received;
+                        missed = 0;
+                    } else if missed == self.max_missed {
+                        tracing::debug!(missed, "peer missed heartbeats");
+                        self.dead = true;
+                        // Ends the reading thread and lets the peer know,
+                        // in case it's still there
+                        let _ = lock(&self.writer).shutdown();
+                        return Err(dead_error());
+                    }
+
+                    missed += 1;
+                    write_frame(&mut *lock(&self.writer), PING, &[])?;
+                }
+            }
+        }
+    }
+}
+
+impl<S: Duplex> Read for Heartbeat<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.data.len() {
+            if self.closed {
+                return Ok(0);
+            }
+            if !self.next_frame()? {
+                return Ok(0);
+            }
+        }
+
+        let len = buf.len().min(self.data.len() - self.position);
+        buf[..len].copy_from_slice(&self.data[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+impl<S: Duplex> Write for Heartbeat<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.dead {
+            return Err(dead_error());
+        }
+
+        self.outgoing.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.dead {
+            return Err(dead_error());
+        }
+
+        if !self.outgoing.is_empty() {
+            write_frame(&mut *lock(&self.writer), DATA, &self.outgoing)?;
+            self.outgoing.clear();
+        }
+
+        Ok(())
+    }
+}
+
+impl<S: Duplex> Drop for Heartbeat<S> {
+    fn drop(&mut self) {
+        let _ = lock(&self.writer).shutdown();
+    }
+}
+
+impl<S: Duplex + PeerAddr> PeerAddr for Heartbeat<S> {
+    fn peer_addr(&self) -> Option<String> {
+        lock(&self.writer).peer_addr()
+    }
+}
+
+fn lock<S>(writer: &Mutex<S>) -> MutexGuard<'_, S> {
+    writer.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn dead_error() -> io::Error {
+    io::Error::new(io::ErrorKind::TimedOut, "peer missed heartbeats")
+}
+
+fn write_frame<W: Write>(writer: &mut W, kind: u8, data: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(5 + data.len());
+    frame.push(kind);
+    if kind == DATA {
+        frame.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        frame.extend_from_slice(data);
+    }
+    writer.write_all(&frame)?;
+    writer.flush()
+}
+
+/// Passes data frames to the [`Heartbeat`] and answers pings, until the
+/// stream ends or the [`Heartbeat`] is dropped.
+fn read_frames<S: Duplex>(
+    mut reader: S,
+    writer: &Mutex<S>,
+    received: &AtomicU64,
+    sender: &Sender<io::Result<Vec<u8>>>,
+) {
+    if let Err(e) = forward_frames(&mut reader, writer, received, sender) {
+        let _ = sender.send(Err(e));
+    }
+}
+
+fn forward_frames<S: Duplex>(
+    reader: &mut S,
+    writer: &Mutex<S>,
+    received: &AtomicU64,
+    sender: &Sender<io::Result<Vec<u8>>>,
+) -> io::Result<()> {
+    loop {
+        let mut kind = [0];
+        if reader.read(&mut kind)? == 0 {
+            return Ok(());
+        }
+        received.fetch_add(1, Ordering::SeqCst);
+
+        match kind[0] {
+            DATA => {
+                let mut len = [0; 4];
+                reader.read_exact(&mut len)?;
+                let len = u32::from_le_bytes(len) as usize;
+                if len > MAX_FRAME_SIZE {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "frame of {} bytes exceeds maximum of {} bytes",
+                            len, MAX_FRAME_SIZE
+                        ),
+                    ));
+                }
+
+                let mut data = vec![0; len];
+                reader.read_exact(&mut data)?;
+                if sender.send(Ok(data)).is_err() {
+                    return Ok(());
+                }
+            }
+            PING => write_frame(&mut *lock(writer), PONG, &[])?,
+            PONG => {}
+            kind => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown frame kind {}", kind),
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,677 @@
+use crate::envelope::{Envelope, Header, ServiceRequest};
+use crate::error::Error;
+use crate::trace::TraceContext;
+use crate::transport::{BincodeCodec, ByteCount, Codec, JsonCodec, PeerAddr, Transport};
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// Requests and responses with longer start line and headers are rejected.
+const MAX_HEAD_LEN: usize = 64 * 1024;
+
+/// Requests and responses with longer body are rejected by default.
+pub const DEFAULT_MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+
+const REQUEST_ID_HEADER: &str = "x-duty-request-id";
+const TRACE_ID_HEADER: &str = "x-duty-trace-id";
+const SPAN_ID_HEADER: &str = "x-duty-span-id";
+
+/// Encoding of request and response bodies, selected by `Content-Type`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Bincode,
+    Json,
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Bincode => "application/x-bincode",
+            Format::Json => "application/json",
+        }
+    }
+
+    fn from_content_type(content_type: &str) -> Option<Format> {
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        [Format::Bincode, Format::Json]
+            .into_iter()
+            .find(|format| format.content_type().eq_ignore_ascii_case(media_type))
+    }
+
+    fn encode<T: Serialize>(&self, data: &T) -> Result<Vec<u8>, Error> {
+        let mut body = Vec::new();
+        match self {
+            Format::Bincode => BincodeCodec::encode(&mut body, data)?,
+            Format::Json => JsonCodec::encode(&mut body, data)?,
+        }
+        Ok(body)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, mut body: &[u8]) -> Result<T, Error> {
+        match self {
+            Format::Bincode => BincodeCodec::decode(&mut body),
+            Format::Json => JsonCodec::decode(&mut body),
+        }
+    }
+}
+
+/// Client side of the HTTP binding.
+///
+/// Every call is sent as `POST /<service>/<method>` with the request in the
+/// body, encoded as it would be by [`crate::transport::Bincode`] or
+/// [`crate::transport::Json`]. Request id and trace context are sent in
+/// `X-Duty-*` headers. Responses with status other than 2xx fail with
+/// [`Error::Http`] carrying the status code and body.
+///
+/// Messages sent with plain [`Transport::send`] are posted to `/`.
+pub struct HttpClient<S> {
+    stream: BufReader<S>,
+    host: String,
+    format: Format,
+    max_body_len: usize,
+    byte_count: ByteCount,
+    peer_addr: Option<String>,
+}
+
+impl<S: Read> HttpClient<S> {
+    pub fn new(stream: S) -> HttpClient<S> {
+        HttpClient {
+            stream: BufReader::new(stream),
+            host: "localhost".to_owned(),
+            format: Format::Bincode,
+            max_body_len: DEFAULT_MAX_BODY_LEN,
+            byte_count: ByteCount::default(),
+            peer_addr: None,
+        }
+    }
+
+    /// Like [`HttpClient::new`], but takes address of the remote side from
+    /// the stream.
+    pub fn from_stream(stream: S) -> HttpClient<S>
+    where
+        S: PeerAddr,
+    {
+        let peer_addr = stream.peer_addr();
+        HttpClient {
+            peer_addr,
+            ..HttpClient::new(stream)
+        }
+    }
+
+    /// Value of the `Host` header, `localhost` by default.
+    pub fn host(mut self, host: impl Into<String>) -> HttpClient<S> {
+        self.host = host.into();
+        self
+    }
+
+    /// Encoding of requests, bincode by default.
+    pub fn format(mut self, format: Format) -> HttpClient<S> {
+        self.format = format;
+        self
+    }
+
+    /// Rejects responses with body longer than `len` bytes instead of
+    /// [`DEFAULT_MAX_BODY_LEN`].
+    pub fn max_body_len(mut self, len: usize) -> HttpClient<S> {
+        self.max_body_len = len;
+        self
+    }
+}
+
+impl<S: Read + Write> HttpClient<S> {
+    fn post(&mut self, path: &str, headers: &[(&str, String)], body: &[u8]) -> Result<(), Error> {
+        let mut all_headers = vec![
+            ("Host", self.host.clone()),
+            ("Content-Type", self.format.content_type().to_owned()),
+        ];
+        all_headers.extend_from_slice(headers);
+
+        let start_line = format!("POST {} HTTP/1.1", path);
+        self.byte_count.sent +=
+            write_message(self.stream.get_mut(), &start_line, &all_headers, body)?;
+        Ok(())
+    }
+}
+
+impl<S: Read + Write + Send + 'static> Transport for HttpClient<S> {
+    fn receive<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        let response = read_message(
+            &mut self.stream,
+            &mut self.byte_count.received,
+            self.max_body_len,
+        )?;
+
+        let status = response
+            .start_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse::<u16>().ok())
+            .ok_or_else(|| {
+                Error::decode(format!("invalid status line: {}", response.start_line))
+            })?;
+
+        if !(200..300).contains(&status) {
+            return Err(Error::Http {
+                status,
+                body: String::from_utf8_lossy(&response.body).into_owned(),
+            });
+        }
+
+        let format = response
+            .header("content-type")
+            .and_then(Format::from_content_type)
+            .unwrap_or(self.format);
+
+        format.decode(&response.body)
+    }
+
+    fn send<T: Serialize>(&mut self, data: &T) -> Result<(), Error> {
+        let body = self.format.encode(data)?;
+        self.post("/", &[], &body)
+    }
+
+    fn call<B: Serialize, Out: DeserializeOwned + Send + 'static>(
+        &mut self,
+        request: &Envelope<B>,
+    ) -> Result<Out, Error> {
+        let header = &request.header;
+        let path = format!(
+            "/{}/{}",
+            encode_path_segment(&header.service),
+            encode_path_segment(&header.method)
+        );
+        let headers = [
+            (REQUEST_ID_HEADER, header.request_id.to_string()),
+            (TRACE_ID_HEADER, format!("{:016x}", header.trace.trace_id)),
+            (SPAN_ID_HEADER, format!("{:016x}", header.trace.span_id)),
+        ];
+
+        let body = self.format.encode(&request.body)?;
+        self.post(&path, &headers, &body)?;
+        self.receive()
+    }
+
+    fn byte_count(&self) -> ByteCount {
+        self.byte_count
+    }
+
+    fn peer_addr(&self) -> Option<&str> {
+        self.peer_addr.as_deref()
+    }
+}
+
+/// Server side of the HTTP binding, see [`HttpClient`].
+///
+/// Each accepted connection is wrapped in its own `HttpServer`, e.g. with
+/// `listener::serve(&tcp_listener, HttpServer::new, ...)`. Requests which
+/// can't be decoded are answered with a 4xx status without reaching the
+/// service, the connection stays open for the next request. Requests with
+/// too long body are answered with `413 Payload Too Large` and close the
+/// connection. Since HTTP
+/// server can only respond, handshakes started by the server side (e.g.
+/// [`crate::auth::HmacAuth`]) are not supported.
+pub struct HttpServer<S> {
+    stream: BufReader<S>,
+    byte_count: ByteCount,
+    /// Format of the request waiting for the response.
+    pending: Option<Format>,
+    /// Client asked to close the connection after the last response.
+    closing: bool,
+    max_body_len: usize,
+    next_request_id: u64,
+}
+
+struct Status {
+    code: u16,
+    reason: &'static str,
+    message: String,
+}
+
+impl Status {
+    fn new(code: u16, reason: &'static str, message: impl Into<String>) -> Status {
+        Status {
+            code,
+            reason,
+            message: message.into(),
+        }
+    }
+}
+
+impl<S: Read> HttpServer<S> {
+    pub fn new(stream: S) -> HttpServer<S> {
+        HttpServer {
+            stream: BufReader::new(stream),
+            byte_count: ByteCount::default(),
+            pending: None,
+            closing: false,
+            max_body_len: DEFAULT_MAX_BODY_LEN,
+            next_request_id: 0,
+        }
+    }
+
+    /// Answers requests with body longer than `len` bytes with
+    /// `413 Payload Too Large` instead of [`DEFAULT_MAX_BODY_LEN`].
+    pub fn max_body_len(mut self, len: usize) -> HttpServer<S> {
+        self.max_body_len = len;
+        self
+    }
+}
+
+impl<S: Read + Write> HttpServer<S> {
+    fn respond(
+        &mut self,
+        code: u16,
+        reason: &str,
+        content_type: &str,
+        body: &[u8],
+    ) -> Result<(), Error> {
+        let start_line = format!("HTTP/1.1 {} {}", code, reason);
+        let mut headers = vec![("Content-Type", content_type.to_owned())];
+        if self.closing {
+            headers.push(("Connection", "close".to_owned()));
+        }
+        self.byte_count.sent += write_message(self.stream.get_mut(), &start_line, &headers, body)?;
+        Ok(())
+    }
+
+    /// Decodes request to the method named by the path. If `methods` of
+    /// the service are given, paths naming other ones are not found.
+    fn decode_request<T: DeserializeOwned>(
+        &mut self,
+        request: &Message,
+        methods: Option<(&str, &[&str])>,
+    ) -> Result<(T, Format), Status> {
+        let mut start_line = request.start_line.split_whitespace();
+        let (method, target) = match (start_line.next(), start_line.next()) {
+            (Some(method), Some(target)) => (method, target),
+            _ => return Err(Status::new(400, "Bad Request", "invalid request line")),
+        };
+
+        if method != "POST" {
+            return Err(Status::new(
+                405,
+                "Method Not Allowed",
+                "only POST is supported",
+            ));
+        }
+
+        let format = request
+            .header("content-type")
+            .and_then(Format::from_content_type)
+            .ok_or_else(|| {
+                Status::new(
+                    415,
+                    "Unsupported Media Type",
+                    format!(
+                        "content type must be {} or {}",
+                        Format::Bincode.content_type(),
+                        Format::Json.content_type()
+                    ),
+                )
+            })?;
+
+        let path = target.split('?').next().unwrap_or_default();
+        let segments = path
+            .trim_start_matches('/')
+            .split('/')
+            .map(decode_path_segment)
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Status::new(400, "Bad Request", "invalid path encoding"))?;
+
+        let decoded = match segments.as_slice() {
+            [root] if root.is_empty() => format.decode(&request.body),
+            [service, method] => {
+                if let Some((known_service, known_methods)) = methods {
+                    if service != known_service || !known_methods.contains(&method.as_str()) {
+                        return Err(Status::new(
+                            404,
+                            "Not Found",
+                            format!("no method {}/{}", service, method),
+                        ));
+                    }
+                }
+
+                let header = self.header(request, service, method);
+                decode_envelope(format, &header, &request.body)
+            }
+            _ => {
+                return Err(Status::new(
+                    404,
+                    "Not Found",
+                    "path must be /<service>/<method>",
+                ))
+            }
+        };
+
+        match decoded {
+            Ok(data) => Ok((data, format)),
+            Err(e) => Err(Status::new(400, "Bad Request", e.to_string())),
+        }
+    }
+
+    fn header(&mut self, request: &Message, service: &str, method: &str) -> Header {
+        let request_id = match request
+            .header(REQUEST_ID_HEADER)
+            .and_then(|id| id.parse().ok())
+        {
+            Some(request_id) => request_id,
+            None => {
+                let request_id = self.next_request_id;
+                self.next_request_id = request_id.wrapping_add(1);
+                request_id
+            }
+        };
+
+        let mut header = Header::new(request_id, service, method);
+
+        let hex_header = |name| {
+            request
+                .header(name)
+                .and_then(|value| u64::from_str_radix(value, 16).ok())
+        };
+        if let (Some(trace_id), Some(span_id)) =
+            (hex_header(TRACE_ID_HEADER), hex_header(SPAN_ID_HEADER))
+        {
+            header.trace = TraceContext { trace_id, span_id };
+        }
+
+        header
+    }
+}
+
+impl<S: Read + Write> HttpServer<S> {
+    /// Reads requests until one is decoded by `decode`, answering the
+    /// others with error statuses.
+    fn receive_with<T>(
+        &mut self,
+        decode: impl Fn(&mut Self, &Message) -> Result<(T, Format), Status>,
+    ) -> Result<T, Error> {
+        loop {
+            if self.closing {
+                // Ends serving the connection, which closes it once the
+                // transport is dropped
+                return Err(Error::ConnectionClosed);
+            }
+
+            let request = match read_message(
+                &mut self.stream,
+                &mut self.byte_count.received,
+                self.max_body_len,
+            ) {
+                Ok(request) => request,
+                Err(Error::Io(e)) if e.kind() == io::ErrorKind::InvalidData => {
+                    // Framing is lost, so the connection can't be used anymore
+                    let (code, reason) = match e.get_ref() {
+                        Some(e) if e.is::<BodyTooLarge>() => (413, "Payload Too Large"),
+                        _ => (400, "Bad Request"),
+                    };
+                    self.closing = true;
+                    let _ = self.respond(code, reason, "text/plain", e.to_string().as_bytes());
+                    return Err(Error::Io(e));
+                }
+                Err(e) => return Err(e),
+            };
+
+            self.closing = request
+                .header("connection")
+                .is_some_and(|connection| connection.eq_ignore_ascii_case("close"));
+
+            match decode(self, &request) {
+                Ok((data, format)) => {
+                    self.pending = Some(format);
+                    return Ok(data);
+                }
+                Err(status) => {
+                    tracing::warn!("invalid HTTP request: {}", status.message);
+                    self.respond(
+                        status.code,
+                        status.reason,
+                        "text/plain",
+                        status.message.as_bytes(),
+                    )?;
+                }
+            }
+        }
+    }
+}
+
+impl<S: Read + Write + Send + 'static> Transport for HttpServer<S> {
+    /// Without knowing the methods of the service, requests of unknown
+    /// methods are answered with `400 Bad Request`, see
+    /// [`Transport::receive_request`].
+    fn receive<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        self.receive_with(|server, request| server.decode_request(request, None))
+    }
+
+    fn receive_request<R>(&mut self) -> Result<Envelope<R>, Error>
+    where
+        R: ServiceRequest + DeserializeOwned + Send + 'static,
+    {
+        self.receive_with(|server, request| {
+            let (request, format): (Envelope<R>, Format) =
+                server.decode_request(request, Some((R::SERVICE, R::METHODS)))?;
+
+            // Body names the method again, which must be the one called
+            let method = request.body.method();
+            if method != request.header.method {
+                return Err(Status::new(
+                    400,
+                    "Bad Request",
+                    format!(
+                        "body is a request of {}, not {}",
+                        method, request.header.method
+                    ),
+                ));
+            }
+
+            Ok((request, format))
+        })
+    }
+
+    fn send<T: Serialize>(&mut self, data: &T) -> Result<(), Error> {
+        let format = self.pending.take().ok_or_else(|| {
+            Error::encode("HTTP server can only send responses to requests".to_owned())
+        })?;
+
+        let body = format.encode(data)?;
+        self.respond(200, "OK", format.content_type(), &body)
+    }
+
+    fn byte_count(&self) -> ByteCount {
+        self.byte_count
+    }
+}
+
+/// Decodes request body with header taken from the path and HTTP headers.
+fn decode_envelope<T: DeserializeOwned>(
+    format: Format,
+    header: &Header,
+    body: &[u8],
+) -> Result<T, Error> {
+    // Both formats can be joined without decoding the body: bincode
+    // encodes structs as their fields one after another, JSON body is
+    // embedded as is
+    let envelope = match format {
+        Format::Bincode => {
+            let mut envelope = format.encode(header)?;
+            envelope.extend_from_slice(body);
+            envelope
+        }
+        Format::Json => {
+            let mut envelope = br#"{"header":"#.to_vec();
+            serde_json::to_writer(&mut envelope, header).map_err(Error::encode)?;
+            envelope.extend_from_slice(br#","body":"#);
+            envelope.extend_from_slice(body);
+            envelope.push(b'}');
+            envelope
+        }
+    };
+
+    format.decode(&envelope)
+}
+
+struct Message {
+    start_line: String,
+    /// Names are lowercase.
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Message {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Body of a message is longer than allowed.
+#[derive(Debug)]
+struct BodyTooLarge {
+    len: usize,
+    max_len: usize,
+}
+
+impl std::fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "body of {} bytes exceeds maximum of {} bytes",
+            self.len, self.max_len
+        )
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
+
+fn read_message<R: BufRead>(
+    reader: &mut R,
+    byte_count: &mut u64,
+    max_body_len: usize,
+) -> Result<Message, Error> {
+    let mut head_len = 0;
+    let mut read_line = |reader: &mut R| -> io::Result<String> {
+        let mut line = String::new();
+        let len = reader
+            .take((MAX_HEAD_LEN - head_len) as u64)
+            .read_line(&mut line)?;
+
+        head_len += len;
+        *byte_count += len as u64;
+
+        match len {
+            0 if head_len == 0 => Err(io::ErrorKind::UnexpectedEof.into()),
+            _ if !line.ends_with('\n') => Err(invalid_data("message head is too long")),
+            _ => Ok(line.trim_end().to_owned()),
+        }
+    };
+
+    let start_line = match read_line(reader) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(Error::ConnectionClosed),
+        line => line?,
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid_data(format!("invalid header: {}", line)))?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+    }
+
+    let mut message = Message {
+        start_line,
+        headers,
+        body: Vec::new(),
+    };
+
+    if message.header("transfer-encoding").is_some() {
+        return Err(invalid_data("transfer encodings are not supported").into());
+    }
+
+    let content_length = match message.header("content-length") {
+        Some(len) => len
+            .parse()
+            .map_err(|_| invalid_data(format!("invalid content length: {}", len)))?,
+        None => 0,
+    };
+
+    if content_length > max_body_len {
+        return Err(invalid_data(BodyTooLarge {
+            len: content_length,
+            max_len: max_body_len,
+        })
+        .into());
+    }
+
+    message.body = vec![0; content_length];
+    reader.read_exact(&mut message.body)?;
+    *byte_count += content_length as u64;
+
+    Ok(message)
+}
+
+/// Writes the whole message, returns its length.
+fn write_message<W: Write>(
+    writer: &mut W,
+    start_line: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> Result<u64, Error> {
+    let mut message = format!("{}\r\n", start_line);
+    for (name, value) in headers {
+        message.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    message.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+
+    let mut message = message.into_bytes();
+    message.extend_from_slice(body);
+
+    writer.write_all(&message)?;
+    writer.flush()?;
+
+    Ok(message.len() as u64)
+}
+
+/// Percent-encodes everything except characters allowed in path segments,
+/// so that e.g. type names used as service names can be sent.
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => encoded.push(byte as char),
+            b'-' | b'.' | b'_' | b'~' | b':' | b'@' | b'!' | b'$' | b'&' | b'\'' | b'(' | b')'
+            | b'*' | b'+' | b',' | b';' | b'=' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn decode_path_segment(segment: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(segment.len());
+    let mut bytes = segment.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'%' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                let hex = std::str::from_utf8(&hex).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            _ => decoded.push(byte),
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn invalid_data<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
@@ -0,0 +1,121 @@
+use crate::envelope::Envelope;
+use crate::error::Error;
+use crate::transport::Transport;
+use serde::{de::DeserializeOwned, Serialize};
+use std::any::Any;
+use std::io;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+enum Message {
+    /// Passed by [`Transport::send_owned`].
+    Value(Box<dyn Any + Send>),
+    /// Passed by [`Transport::send`], which has only a reference to the
+    /// message.
+    Encoded(Vec<u8>),
+}
+
+/// Transport for client and server living in the same process.
+///
+/// Requests and responses of generated clients and services are passed
+/// through a channel as they are, without serialization, so that calls cost
+/// little more than a function call. Messages sent by reference (e.g. by
+/// [`crate::server::RequestHandle::respond`], authentication handshakes or
+/// requests with borrowed arguments) fall back to bincode.
+pub struct InProcess {
+    sender: Sender<Message>,
+    receiver: Receiver<Message>,
+}
+
+impl InProcess {
+    pub fn new_pair() -> (InProcess, InProcess) {
+        let (send1, recv1) = channel();
+        let (send2, recv2) = channel();
+        (
+            InProcess {
+                sender: send1,
+                receiver: recv2,
+            },
+            InProcess {
+                sender: send2,
+                receiver: recv1,
+            },
+        )
+    }
+
+    fn send_message(&mut self, message: Message) -> Result<(), Error> {
+        self.sender.send(message).map_err(|_| {
+            io::Error::new(io::ErrorKind::BrokenPipe, "Cannot send data: peer dropped")
+        })?;
+        Ok(())
+    }
+
+    fn receive_message(&mut self) -> Result<Message, Error> {
+        self.receiver.recv().map_err(|_| Error::ConnectionClosed)
+    }
+}
+
+impl Transport for InProcess {
+    fn receive<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        match self.receive_message()? {
+            Message::Encoded(data) => decode(&data),
+            Message::Value(_) => Err(Error::decode(
+                "value passed in-process can only be received with receive_owned".to_owned(),
+            )),
+        }
+    }
+
+    fn send<T: Serialize>(&mut self, data: &T) -> Result<(), Error> {
+        let data = bincode::serialize(data).map_err(|e| Error::from_bincode(e, Error::Encode))?;
+        self.send_message(Message::Encoded(data))
+    }
+
+    fn send_owned<T: Serialize + Send + 'static>(&mut self, data: T) -> Result<(), Error> {
+        self.send_message(Message::Value(Box::new(data)))
+    }
+
+    fn receive_owned<T: DeserializeOwned + Send + 'static>(&mut self) -> Result<T, Error> {
+        match self.receive_message()? {
+            Message::Encoded(data) => decode(&data),
+            Message::Value(value) => value.downcast().map(|value| *value).map_err(|_| {
+                Error::decode(format!(
+                    "expected value of type {}",
+                    std::any::type_name::<T>()
+                ))
+            }),
+        }
+    }
+
+    fn call<B: Serialize, Out: DeserializeOwned + Send + 'static>(
+        &mut self,
+        request: &Envelope<B>,
+    ) -> Result<Out, Error> {
+        self.send(request)?;
+        self.receive_owned()
+    }
+
+    fn call_owned<B, Out>(&mut self, request: Envelope<B>) -> Result<Out, Error>
+    where
+        B: Serialize + Send + 'static,
+        Out: DeserializeOwned + Send + 'static,
+    {
+        self.send_owned(request)?;
+        self.receive_owned()
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        // Peer sees the channel disconnected once our sender is gone
+        let (sender, _) = channel();
+        self.sender = sender;
+        Ok(())
+    }
+
+    /// The peer lives in the same process, which is reported instead of an
+    /// address.
+    fn peer_addr(&self) -> Option<&str> {
+        Some("in-process")
+    }
+}
+
+fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
+    bincode::deserialize(data).map_err(|e| Error::from_bincode(e, Error::Decode))
+}
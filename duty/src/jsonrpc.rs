@@ -0,0 +1,378 @@
+use crate::envelope::{Envelope, Header, ServiceRequest};
+use crate::error::Error;
+use crate::transport::{ByteCount, Counting, PeerAddr, Transport};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Map, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+
+/// Messages longer than this are rejected by default.
+pub const DEFAULT_MAX_LINE_LEN: usize = 16 * 1024 * 1024;
+
+/// JSON-RPC 2.0 over newline delimited JSON.
+///
+/// Method names are the names of the service trait methods and params are
+/// either an object with the named arguments or an array with them in
+/// declaration order, e.g.
+///
+/// ```text
+/// {"jsonrpc": "2.0", "method": "and", "params": {"a": true, "b": false}, "id": 1}
+/// ```
+///
+/// Server side answers malformed requests with standard error codes and
+/// keeps waiting for the next one. Notifications (requests without id) are
+/// handled, but their responses are dropped. Batches are not supported.
+/// Messages longer than the maximum line length fail with
+/// [`Error::Decode`], which ends the connection.
+pub struct JsonRpc<S> {
+    stream: BufReader<S>,
+    max_line_len: usize,
+    byte_count: ByteCount,
+    state: State,
+    next_request_id: u64,
+    peer_addr: Option<String>,
+}
+
+enum State {
+    Idle,
+    /// Client sent request with this id and waits for the response.
+    AwaitingResponse(Value),
+    /// Server received request with this id and owes the response.
+    Responding(Value),
+    /// Server received notification, its response is not sent.
+    Notified,
+}
+
+struct RpcError {
+    id: Value,
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(id: Value, code: i64, message: impl Into<String>) -> RpcError {
+        RpcError {
+            id,
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Request parsed up to its params, which are decoded as the fields of the
+/// request variant named after the method.
+struct Call {
+    id: Option<Value>,
+    request_id: u64,
+    method: String,
+    params: Value,
+}
+
+impl Call {
+    fn error_id(&self) -> Value {
+        self.id.clone().unwrap_or(Value::Null)
+    }
+
+    /// Decodes envelope with header naming `service` and `method`.
+    fn decode<T: DeserializeOwned>(&self, service: &str, method: &str) -> Result<T, RpcError> {
+        // Request enums generated by the service macro name their variants
+        // after the trait methods, so params deserialize as the variant's
+        // fields. Going through text, as unlike `Value` it accepts struct
+        // variants given as arrays.
+        let envelope = json!({
+            "header": Header::new(self.request_id, service, method),
+            "body": { self.method.as_str(): self.params },
+        });
+
+        serde_json::from_str(&envelope.to_string())
+            .map_err(|e| RpcError::new(self.error_id(), INVALID_PARAMS, e.to_string()))
+    }
+}
+
+impl<S: Read> JsonRpc<S> {
+    pub fn new(stream: S) -> JsonRpc<S> {
+        JsonRpc {
+            stream: BufReader::new(stream),
+            max_line_len: DEFAULT_MAX_LINE_LEN,
+            byte_count: ByteCount::default(),
+            state: State::Idle,
+            next_request_id: 0,
+            peer_addr: None,
+        }
+    }
+
+    /// Like [`JsonRpc::new`], but takes address of the remote side from the
+    /// stream.
+    pub fn from_stream(stream: S) -> JsonRpc<S>
+    where
+        S: PeerAddr,
+    {
+        let peer_addr = stream.peer_addr();
+        JsonRpc {
+            peer_addr,
+            ..JsonRpc::new(stream)
+        }
+    }
+
+    /// Rejects messages longer than `len` bytes instead of
+    /// [`DEFAULT_MAX_LINE_LEN`].
+    pub fn max_line_len(mut self, len: usize) -> JsonRpc<S> {
+        self.max_line_len = len;
+        self
+    }
+}
+
+impl<S: Read + Write> JsonRpc<S> {
+    fn read_line(&mut self) -> Result<Vec<u8>, Error> {
+        let mut line = Vec::new();
+        let len = (&mut self.stream)
+            .take(self.max_line_len as u64 + 1)
+            .read_until(b'\n', &mut line)?;
+        self.byte_count.received += len as u64;
+
+        if len == 0 {
+            return Err(Error::ConnectionClosed);
+        }
+
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        } else if line.len() > self.max_line_len {
+            return Err(Error::decode(format!(
+                "message exceeds maximum of {} bytes",
+                self.max_line_len
+            )));
+        }
+        Ok(line)
+    }
+
+    fn write_message(&mut self, message: &Value) -> Result<(), Error> {
+        let mut writer = Counting::new(self.stream.get_mut(), &mut self.byte_count.sent);
+        serde_json::to_writer(&mut writer, message)
+            .map_err(|e| Error::from_json(e, Error::Encode))?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn parse_call(&mut self, line: &[u8]) -> Result<Call, RpcError> {
+        let request: Value = serde_json::from_slice(line)
+            .map_err(|e| RpcError::new(Value::Null, PARSE_ERROR, e.to_string()))?;
+
+        let mut request = match request {
+            Value::Object(request) => request,
+            Value::Array(_) => {
+                return Err(RpcError::new(
+                    Value::Null,
+                    INVALID_REQUEST,
+                    "batch requests are not supported",
+                ))
+            }
+            _ => {
+                return Err(RpcError::new(
+                    Value::Null,
+                    INVALID_REQUEST,
+                    "request must be an object",
+                ))
+            }
+        };
+
+        let id = request.remove("id");
+        let error_id = id.clone().unwrap_or(Value::Null);
+
+        if request.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+            return Err(RpcError::new(
+                error_id,
+                INVALID_REQUEST,
+                "jsonrpc member must be \"2.0\"",
+            ));
+        }
+
+        let method = match request.remove("method") {
+            Some(Value::String(method)) => method,
+            _ => {
+                return Err(RpcError::new(
+                    error_id,
+                    INVALID_REQUEST,
+                    "method member must be a string",
+                ))
+            }
+        };
+
+        let params = match request.remove("params") {
+            None => Value::Object(Map::new()),
+            Some(params @ (Value::Object(_) | Value::Array(_))) => params,
+            Some(_) => {
+                return Err(RpcError::new(
+                    error_id,
+                    INVALID_PARAMS,
+                    "params must be an object or an array",
+                ))
+            }
+        };
+
+        let request_id = match id.as_ref().and_then(Value::as_u64) {
+            Some(id) => id,
+            None => {
+                let request_id = self.next_request_id;
+                self.next_request_id = request_id.wrapping_add(1);
+                request_id
+            }
+        };
+
+        Ok(Call {
+            id,
+            request_id,
+            method,
+            params,
+        })
+    }
+
+    /// Reads requests until one is decoded by `decode`, answering the
+    /// others with errors.
+    fn receive_call<T>(
+        &mut self,
+        decode: impl Fn(&Call) -> Result<T, RpcError>,
+    ) -> Result<T, Error> {
+        loop {
+            let line = self.read_line()?;
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+
+            match self
+                .parse_call(&line)
+                .and_then(|call| Ok((decode(&call)?, call.id)))
+            {
+                Ok((request, Some(id))) => {
+                    self.state = State::Responding(id);
+                    return Ok(request);
+                }
+                Ok((request, None)) => {
+                    self.state = State::Notified;
+                    return Ok(request);
+                }
+                Err(error) => {
+                    tracing::warn!("invalid JSON-RPC request: {}", error.message);
+                    self.write_message(&json!({
+                        "jsonrpc": "2.0",
+                        "error": { "code": error.code, "message": error.message },
+                        "id": error.id,
+                    }))?;
+                }
+            }
+        }
+    }
+
+    fn receive_response<T: DeserializeOwned>(&mut self, id: &Value) -> Result<T, Error> {
+        let line = self.read_line()?;
+        let mut response: Map<String, Value> =
+            serde_json::from_slice(&line).map_err(Error::decode)?;
+
+        // Errors for unparsable requests come with null id
+        match response.get("id") {
+            Some(response_id) if response_id == id || response_id.is_null() => {}
+            _ => return Err(Error::decode(format!("expected response with id {}", id))),
+        }
+
+        if let Some(error) = response.remove("error") {
+            return Err(Error::Remote {
+                code: error.get("code").and_then(Value::as_i64).unwrap_or(0),
+                message: error
+                    .get("message")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_owned(),
+            });
+        }
+
+        let result = response
+            .remove("result")
+            .ok_or_else(|| Error::decode("response has no result".to_owned()))?;
+
+        serde_json::from_value(result).map_err(Error::decode)
+    }
+
+    fn send_request<T: Serialize>(&mut self, data: &T) -> Result<(), Error> {
+        let request = serde_json::to_value(data).map_err(Error::encode)?;
+
+        let id = request
+            .pointer("/header/request_id")
+            .cloned()
+            .unwrap_or(Value::Null);
+
+        let (method, params) = match request.get("body") {
+            Some(Value::Object(body)) if body.len() == 1 => body.iter().next().unwrap(),
+            _ => {
+                return Err(Error::encode(
+                    "JSON-RPC transport can only send service requests".to_owned(),
+                ))
+            }
+        };
+
+        self.write_message(&json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": id,
+        }))?;
+
+        self.state = State::AwaitingResponse(id);
+        Ok(())
+    }
+}
+
+impl<S: Read + Write + Send + 'static> Transport for JsonRpc<S> {
+    /// Without knowing the methods of the service, requests of unknown
+    /// methods are answered as having invalid params, see
+    /// [`Transport::receive_request`].
+    fn receive<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        if let State::AwaitingResponse(id) = std::mem::replace(&mut self.state, State::Idle) {
+            return self.receive_response(&id);
+        }
+
+        self.receive_call(|call| call.decode("", &call.method))
+    }
+
+    fn receive_request<R>(&mut self) -> Result<Envelope<R>, Error>
+    where
+        R: ServiceRequest + DeserializeOwned + Send + 'static,
+    {
+        self.receive_call(|call| {
+            if !R::VARIANTS.contains(&call.method.as_str()) {
+                return Err(RpcError::new(
+                    call.error_id(),
+                    METHOD_NOT_FOUND,
+                    format!("method not found: {}", call.method),
+                ));
+            }
+
+            // Instances of generic methods share the method name
+            let mut request: Envelope<R> = call.decode(R::SERVICE, "")?;
+            request.header.method = request.body.method().to_owned();
+            Ok(request)
+        })
+    }
+
+    fn send<T: Serialize>(&mut self, data: &T) -> Result<(), Error> {
+        match std::mem::replace(&mut self.state, State::Idle) {
+            State::Responding(id) => {
+                let result = serde_json::to_value(data).map_err(Error::encode)?;
+                self.write_message(&json!({ "jsonrpc": "2.0", "result": result, "id": id }))
+            }
+            State::Notified => Ok(()),
+            State::Idle | State::AwaitingResponse(_) => self.send_request(data),
+        }
+    }
+
+    fn byte_count(&self) -> ByteCount {
+        self.byte_count
+    }
+
+    fn peer_addr(&self) -> Option<&str> {
+        self.peer_addr.as_deref()
+    }
+}
@@ -1,10 +1,67 @@
+pub mod auth;
 pub mod client;
+#[cfg(any(feature = "lz4", feature = "zstd"))]
+pub mod compression;
 pub mod dispatcher;
+pub mod envelope;
 pub mod error;
+pub mod heartbeat;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod inprocess;
+pub mod jsonrpc;
+pub mod listener;
+pub mod metrics;
 pub mod procedure;
+pub mod process;
+pub mod reconnect;
 pub mod server;
+#[cfg(all(feature = "shm", target_os = "linux"))]
+pub mod shm;
+#[cfg(feature = "ssh")]
+pub mod ssh;
 pub mod stream;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod trace;
 pub mod transport;
+#[cfg(unix)]
+pub mod unix;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+/// Items used by code generated by the `service` macro.
+///
+/// Generic methods of clients can't be called with types the service has no
+/// instance for:
+///
+/// ```compile_fail,E0277
+/// use serde::{de::DeserializeOwned, Serialize};
+///
+/// #[duty::service]
+/// trait Statistics {
+///     #[duty(instance(i64), instance(f64))]
+///     fn sum<T: Serialize + DeserializeOwned>(&self, values: Vec<T>) -> T;
+/// }
+///
+/// fn call(client: StatisticsClient<duty::transport::Bincode<duty::stream::MpscStream>>) {
+///     let _ = client.sum(vec![1u8, 2]);
+/// }
+/// ```
+#[doc(hidden)]
+pub mod private {
+    pub use serde;
+
+    use std::any::Any;
+
+    /// Moves value between two names of the same type, e.g. type parameter
+    /// and the type it was instantiated with.
+    pub fn cast<A: 'static, B: 'static>(value: A) -> B {
+        *(Box::new(value) as Box<dyn Any>)
+            .downcast()
+            .expect("cast between different types")
+    }
+}
 
 pub use crate::error::Error;
 pub use crate::transport::Transport;
@@ -0,0 +1,400 @@
+use crate::auth::{Authenticator, NoAuth};
+use crate::error::Error;
+use crate::transport::Transport;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Wakes up a listener blocked in [`Listener::accept`].
+pub type Waker = Box<dyn Fn() + Send + Sync>;
+
+/// Source of incoming connections.
+pub trait Listener: Send + 'static {
+    type Stream: Read + Write + Send + 'static;
+
+    fn accept(&self) -> io::Result<Self::Stream>;
+
+    /// Returns function making pending [`Listener::accept`] return, used to
+    /// stop serving on [`Shutdown`]. Listeners which can't be woken up fail
+    /// with [`io::ErrorKind::Unsupported`].
+    fn waker(&self) -> io::Result<Waker> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    fn accept(&self) -> io::Result<TcpStream> {
+        TcpListener::accept(self).map(|(stream, _)| stream)
+    }
+
+    fn waker(&self) -> io::Result<Waker> {
+        let mut addr = self.local_addr()?;
+
+        // Listening on all interfaces includes the loopback
+        if addr.ip().is_unspecified() {
+            let loopback = match addr {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            };
+            addr.set_ip(loopback);
+        }
+
+        Ok(Box::new(move || {
+            let _ = TcpStream::connect(addr);
+        }))
+    }
+}
+
+#[cfg(unix)]
+impl Listener for std::os::unix::net::UnixListener {
+    type Stream = std::os::unix::net::UnixStream;
+
+    fn accept(&self) -> io::Result<Self::Stream> {
+        std::os::unix::net::UnixListener::accept(self).map(|(stream, _)| stream)
+    }
+
+    fn waker(&self) -> io::Result<Waker> {
+        let path = self
+            .local_addr()?
+            .as_pathname()
+            .ok_or(io::ErrorKind::Unsupported)?
+            .to_path_buf();
+
+        Ok(Box::new(move || {
+            let _ = std::os::unix::net::UnixStream::connect(&path);
+        }))
+    }
+}
+
+/// Accepts connections and serves each of them in a separate thread.
+///
+/// `make_transport` wraps accepted stream in a transport, `serve_connection`
+/// handles requests until the connection is over. Both are called in the
+/// connection's thread, so slow handshakes don't block accepting other
+/// connections. Failures of accepting a single connection, like aborted
+/// connections or running out of file descriptors, are logged and skipped.
+/// Returns only if accepting fails for good.
+pub fn serve<L, T, M, S>(listener: &L, make_transport: M, serve_connection: S) -> io::Result<()>
+where
+    L: Listener,
+    T: Transport,
+    M: Fn(L::Stream) -> T + Send + Sync + 'static,
+    S: Fn(T) -> Result<(), Error> + Send + Sync + 'static,
+{
+    ServerBuilder::new().serve(listener, make_transport, serve_connection)
+}
+
+/// Like [`serve`], but hands the accepted stream directly to
+/// `serve_connection`, e.g. when building the transport involves a
+/// handshake which may fail.
+pub fn serve_streams<L, S>(listener: &L, serve_connection: S) -> io::Result<()>
+where
+    L: Listener,
+    S: Fn(L::Stream) -> Result<(), Error> + Send + Sync + 'static,
+{
+    ServerBuilder::new().serve_streams(listener, serve_connection)
+}
+
+type ConnectHook = Arc<dyn Fn(u64) + Send + Sync>;
+type DisconnectHook = Arc<dyn Fn(u64, &Result<(), Error>) + Send + Sync>;
+
+/// Options of [`serve`]: limit of connections, shutdown, connection hooks
+/// and authentication of clients.
+#[derive(Default)]
+pub struct ServerBuilder<A = NoAuth> {
+    max_connections: Option<usize>,
+    shutdown: Option<Shutdown>,
+    on_connect: Option<ConnectHook>,
+    on_disconnect: Option<DisconnectHook>,
+    authenticator: Arc<A>,
+}
+
+impl ServerBuilder {
+    pub fn new() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// See [`serve_streams`]. Returns also when shut down. Available only
+    /// without authenticator, which needs a transport to run on.
+    pub fn serve_streams<L, S>(&self, listener: &L, serve_connection: S) -> io::Result<()>
+    where
+        L: Listener,
+        S: Fn(L::Stream) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        self.accept_connections(listener, serve_connection)
+    }
+}
+
+impl<A> ServerBuilder<A> {
+    /// Serves at most `max` connections at once, the next connection is
+    /// accepted once one of them ends. Unlimited by default.
+    pub fn max_connections(mut self, max: usize) -> ServerBuilder<A> {
+        self.max_connections = Some(max.max(1));
+        self
+    }
+
+    /// Stops accepting connections once `shutdown` is triggered. Connections
+    /// being served are left to end on their own.
+    pub fn shutdown(mut self, shutdown: &Shutdown) -> ServerBuilder<A> {
+        self.shutdown = Some(shutdown.clone());
+        self
+    }
+
+    /// Calls `hook` with the number of each accepted connection, in the
+    /// thread serving it.
+    pub fn on_connect<F>(mut self, hook: F) -> ServerBuilder<A>
+    where
+        F: Fn(u64) + Send + Sync + 'static,
+    {
+        self.on_connect = Some(Arc::new(hook));
+        self
+    }
+
+    /// Calls `hook` with the number of connection and the result it ended
+    /// with, in the thread which served it.
+    pub fn on_disconnect<F>(mut self, hook: F) -> ServerBuilder<A>
+    where
+        F: Fn(u64, &Result<(), Error>) + Send + Sync + 'static,
+    {
+        self.on_disconnect = Some(Arc::new(hook));
+        self
+    }
+
+    /// Verifies every client with `authenticator` before its first request.
+    /// Rejected clients end their connection with
+    /// [`Error::Unauthenticated`].
+    pub fn auth<B: Authenticator>(self, authenticator: B) -> ServerBuilder<B> {
+        ServerBuilder {
+            max_connections: self.max_connections,
+            shutdown: self.shutdown,
+            on_connect: self.on_connect,
+            on_disconnect: self.on_disconnect,
+            authenticator: Arc::new(authenticator),
+        }
+    }
+
+    /// See [`serve`]. Returns also when shut down.
+    pub fn serve<L, T, M, S>(
+        &self,
+        listener: &L,
+        make_transport: M,
+        serve_connection: S,
+    ) -> io::Result<()>
+    where
+        A: Authenticator + Send + Sync + 'static,
+        L: Listener,
+        T: Transport,
+        M: Fn(L::Stream) -> T + Send + Sync + 'static,
+        S: Fn(T) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        let authenticator = self.authenticator.clone();
+        self.accept_connections(listener, move |stream| {
+            let mut transport = make_transport(stream);
+            authenticator.verify(&mut transport)?;
+            serve_connection(transport)
+        })
+    }
+
+    fn accept_connections<L, S>(&self, listener: &L, serve_connection: S) -> io::Result<()>
+    where
+        L: Listener,
+        S: Fn(L::Stream) -> Result<(), Error> + Send + Sync + 'static,
+    {
+        let slots = self.max_connections.map(Slots::new);
+
+        if let Some(shutdown) = &self.shutdown {
+            let wake_listener = listener.waker()?;
+            let slots = slots.clone();
+            // Server may be waiting for a connection to end rather than
+            // accepting
+            shutdown.set_waker(Box::new(move || {
+                if let Some(slots) = &slots {
+                    slots.wake_all();
+                }
+                wake_listener();
+            }));
+        }
+
+        let serve_connection = Arc::new(serve_connection);
+        let next_connection = AtomicU64::new(0);
+
+        loop {
+            let slot = match &slots {
+                Some(slots) => match slots.acquire(|| self.is_shut_down()) {
+                    Some(slot) => Some(slot),
+                    None => return Ok(()),
+                },
+                None => None,
+            };
+
+            if self.is_shut_down() {
+                return Ok(());
+            }
+
+            let stream = listener.accept();
+
+            if self.is_shut_down() {
+                return Ok(());
+            }
+
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) if is_transient(&e) => {
+                    tracing::warn!("accepting connection failed: {}", e);
+                    if is_out_of_resources(&e) {
+                        // Give connections being served time to end
+                        std::thread::sleep(RESOURCES_BACKOFF);
+                    }
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let connection = next_connection.fetch_add(1, Ordering::Relaxed);
+            let serve_connection = serve_connection.clone();
+            let on_connect = self.on_connect.clone();
+            let on_disconnect = self.on_disconnect.clone();
+
+            std::thread::spawn(move || {
+                if let Some(on_connect) = on_connect {
+                    on_connect(connection);
+                }
+
+                let result = serve_connection(stream);
+                if let Err(e) = &result {
+                    tracing::debug!("connection ended: {}", e);
+                }
+
+                if let Some(on_disconnect) = on_disconnect {
+                    on_disconnect(connection, &result);
+                }
+
+                drop(slot);
+            });
+        }
+    }
+
+    fn is_shut_down(&self) -> bool {
+        self.shutdown.as_ref().is_some_and(Shutdown::is_shut_down)
+    }
+}
+
+/// Pause after accepting failed because of exhausted resources.
+const RESOURCES_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Whether accepting failed only for the single connection or for the time
+/// being, so that the next connection can still be accepted.
+fn is_transient(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+    ) || is_out_of_resources(error)
+}
+
+/// Whether there are too many open files or too little memory.
+fn is_out_of_resources(error: &io::Error) -> bool {
+    if error.kind() == io::ErrorKind::OutOfMemory {
+        return true;
+    }
+
+    #[cfg(unix)]
+    if let Some(code) = error.raw_os_error() {
+        return matches!(
+            code,
+            libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM
+        );
+    }
+
+    false
+}
+
+/// Handle stopping [`ServerBuilder::serve`] from another thread.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    inner: Arc<ShutdownInner>,
+}
+
+#[derive(Default)]
+struct ShutdownInner {
+    triggered: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        Shutdown::default()
+    }
+
+    /// Makes the server return as soon as it's done accepting the current
+    /// connection, if any, or right away if it waits for a connection to
+    /// end.
+    pub fn trigger(&self) {
+        self.inner.triggered.store(true, Ordering::SeqCst);
+
+        let waker = self.inner.waker.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(wake) = waker.as_ref() {
+            wake();
+        }
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        self.inner.triggered.load(Ordering::SeqCst)
+    }
+
+    fn set_waker(&self, waker: Waker) {
+        *self.inner.waker.lock().unwrap_or_else(|e| e.into_inner()) = Some(waker);
+    }
+}
+
+/// Limits number of connections served at once.
+struct Slots {
+    free: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Taken by a connection until it ends.
+struct Slot(Arc<Slots>);
+
+impl Slots {
+    fn new(count: usize) -> Arc<Slots> {
+        Arc::new(Slots {
+            free: Mutex::new(count),
+            released: Condvar::new(),
+        })
+    }
+
+    /// Waits for a free slot. Returns `None` once `is_shut_down`, checked
+    /// whenever woken up by [`Slots::wake_all`].
+    fn acquire(self: &Arc<Slots>, is_shut_down: impl Fn() -> bool) -> Option<Slot> {
+        let mut free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+        while *free == 0 {
+            if is_shut_down() {
+                return None;
+            }
+            free = self.released.wait(free).unwrap_or_else(|e| e.into_inner());
+        }
+        *free -= 1;
+        Some(Slot(self.clone()))
+    }
+
+    fn wake_all(&self) {
+        // Taking the lock makes sure a waiter which has just checked for
+        // shutdown is already waiting
+        let _free = self.free.lock().unwrap_or_else(|e| e.into_inner());
+        self.released.notify_all();
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.0.free.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        self.0.released.notify_one();
+    }
+}
@@ -0,0 +1,323 @@
+use crate::envelope::Header;
+use crate::error::Error;
+use crate::transport::{ByteCount, Transport};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Upper bounds (in seconds) of latency histogram buckets.
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 60.0,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Client,
+    Server,
+}
+
+impl Side {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Side::Client => "client",
+            Side::Server => "server",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    pub bounds: &'static [f64],
+    /// Number of observations in each bucket. The last element counts the
+    /// observations above the highest bound.
+    pub counts: Vec<u64>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Histogram {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// Metrics of a single method as seen by one side of the connection.
+///
+/// On the client side `latency` is the whole round trip, on the server side
+/// it is the time spent in the method implementation. The difference between
+/// the two is the time spent in serialization and in the transport.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MethodMetrics {
+    pub side: Side,
+    pub service: String,
+    pub method: String,
+    pub calls: u64,
+    pub errors: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub latency: Histogram,
+}
+
+type Key = (Side, String, String);
+
+fn registry() -> &'static Mutex<HashMap<Key, MethodMetrics>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<Key, MethodMetrics>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+fn record(side: Side, header: &Header, latency: Duration, bytes: ByteCount, is_error: bool) {
+    let mut registry = registry().lock().expect("Mutex is poisoned");
+    let metrics = registry
+        .entry((side, header.service.clone(), header.method.clone()))
+        .or_insert_with(|| MethodMetrics {
+            side,
+            service: header.service.clone(),
+            method: header.method.clone(),
+            calls: 0,
+            errors: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            latency: Histogram::new(LATENCY_BUCKETS),
+        });
+
+    metrics.calls += 1;
+    if is_error {
+        metrics.errors += 1;
+    }
+    metrics.bytes_sent += bytes.sent;
+    metrics.bytes_received += bytes.received;
+    metrics.latency.observe(latency.as_secs_f64());
+}
+
+/// Returns metrics of all methods called so far, sorted by side, service
+/// and method name.
+pub fn snapshot() -> Vec<MethodMetrics> {
+    let registry = registry().lock().expect("Mutex is poisoned");
+    let mut snapshot: Vec<_> = registry.values().cloned().collect();
+    snapshot.sort_by(|a, b| {
+        (a.side.as_str(), &a.service, &a.method).cmp(&(b.side.as_str(), &b.service, &b.method))
+    });
+    snapshot
+}
+
+pub fn reset() {
+    registry().lock().expect("Mutex is poisoned").clear();
+}
+
+/// Measures single call. Created right before the request is sent (client)
+/// or right after it is received (server).
+pub struct Recorder {
+    side: Side,
+    header: Header,
+    start: Instant,
+    start_bytes: ByteCount,
+}
+
+impl Recorder {
+    pub fn start(side: Side, header: &Header, start_bytes: ByteCount) -> Recorder {
+        Recorder {
+            side,
+            header: header.clone(),
+            start: Instant::now(),
+            start_bytes,
+        }
+    }
+
+    pub fn finish<R>(self, transport: &impl Transport, result: &Result<R, Error>) {
+        let latency = self.start.elapsed();
+        self.finish_with_latency(transport, latency, result.is_err());
+    }
+
+    /// Stops measuring latency and sends the response.
+    pub fn respond<T: Transport, R: Serialize>(
+        self,
+        transport: &mut T,
+        response: &R,
+    ) -> Result<(), Error> {
+        let latency = self.start.elapsed();
+        let result = transport.send(response);
+        self.finish_with_latency(transport, latency, result.is_err());
+        result
+    }
+
+    /// Like [`Recorder::respond`], but sends the response with
+    /// [`Transport::send_owned`].
+    pub fn respond_owned<T: Transport, R: Serialize + Send + 'static>(
+        self,
+        transport: &mut T,
+        response: R,
+    ) -> Result<(), Error> {
+        let latency = self.start.elapsed();
+        let result = transport.send_owned(response);
+        self.finish_with_latency(transport, latency, result.is_err());
+        result
+    }
+
+    fn finish_with_latency(self, transport: &impl Transport, latency: Duration, is_error: bool) {
+        let bytes = transport.byte_count();
+        let bytes = ByteCount {
+            sent: bytes.sent.saturating_sub(self.start_bytes.sent),
+            received: bytes.received.saturating_sub(self.start_bytes.received),
+        };
+        record(self.side, &self.header, latency, bytes, is_error);
+    }
+}
+
+/// Formats metrics in Prometheus text exposition format.
+pub fn to_prometheus(snapshot: &[MethodMetrics]) -> String {
+    let mut output = String::new();
+
+    write_counter(
+        &mut output,
+        snapshot,
+        "duty_calls_total",
+        "Number of calls.",
+        |m| m.calls,
+    );
+    write_counter(
+        &mut output,
+        snapshot,
+        "duty_errors_total",
+        "Number of failed calls.",
+        |m| m.errors,
+    );
+    write_counter(
+        &mut output,
+        snapshot,
+        "duty_sent_bytes_total",
+        "Number of bytes sent.",
+        |m| m.bytes_sent,
+    );
+    write_counter(
+        &mut output,
+        snapshot,
+        "duty_received_bytes_total",
+        "Number of bytes received.",
+        |m| m.bytes_received,
+    );
+
+    let name = "duty_latency_seconds";
+    let _ = writeln!(output, "# HELP {name} Call latency.");
+    let _ = writeln!(output, "# TYPE {name} histogram");
+    for metrics in snapshot {
+        let labels = labels(metrics);
+        let histogram = &metrics.latency;
+        let mut cumulative = 0;
+        for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                output,
+                "{name}_bucket{{{labels},le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            output,
+            "{name}_bucket{{{labels},le=\"+Inf\"}} {}",
+            histogram.count
+        );
+        let _ = writeln!(output, "{name}_sum{{{labels}}} {}", histogram.sum);
+        let _ = writeln!(output, "{name}_count{{{labels}}} {}", histogram.count);
+    }
+
+    output
+}
+
+fn write_counter(
+    output: &mut String,
+    snapshot: &[MethodMetrics],
+    name: &str,
+    help: &str,
+    value: impl Fn(&MethodMetrics) -> u64,
+) {
+    let _ = writeln!(output, "# HELP {name} {help}");
+    let _ = writeln!(output, "# TYPE {name} counter");
+    for metrics in snapshot {
+        let _ = writeln!(output, "{name}{{{}}} {}", labels(metrics), value(metrics));
+    }
+}
+
+fn labels(metrics: &MethodMetrics) -> String {
+    format!(
+        "side=\"{}\",service=\"{}\",method=\"{}\"",
+        metrics.side.as_str(),
+        escape_label(&metrics.service),
+        escape_label(&metrics.method)
+    )
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Minimal HTTP server answering every request with current metrics in
+/// Prometheus text format.
+pub struct PrometheusExporter {
+    listener: TcpListener,
+}
+
+impl PrometheusExporter {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<PrometheusExporter> {
+        Ok(PrometheusExporter {
+            listener: TcpListener::bind(addr)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves metrics in a background thread.
+    pub fn spawn(self) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            for connection in self.listener.incoming() {
+                let result = connection.and_then(respond_with_metrics);
+                if let Err(e) = result {
+                    tracing::warn!("metrics exporter: {}", e);
+                }
+            }
+        })
+    }
+}
+
+fn respond_with_metrics(mut stream: TcpStream) -> io::Result<()> {
+    // We answer every path, so request headers are read only to keep the
+    // client happy
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line != "\r\n" && line != "\n" {
+        line.clear();
+    }
+
+    let body = to_prometheus(&snapshot());
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -59,4 +59,8 @@ mod test {
             MathRequest::ProductProcedure(r)
         }
     }
+
+    // Procedures sharing a request enum convert into it
+    const _: fn(SumProcedure, ProductProcedure) -> [MathRequest; 2] =
+        |sum, product| [sum.into(), product.into()];
 }
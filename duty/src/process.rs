@@ -0,0 +1,272 @@
+use crate::dispatcher::Dispatcher;
+use crate::envelope::Envelope;
+use crate::error::Error;
+use crate::transport::{ByteCount, PeerIdentity, Transport};
+use serde::{de::DeserializeOwned, Serialize};
+use std::ffi::{OsStr, OsString};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::Arc;
+
+/// Stdin and stdout of a child process used as a single stream.
+pub struct ChildStream {
+    stdout: ChildStdout,
+    stdin: ChildStdin,
+}
+
+impl Read for ChildStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdout.read(buf)
+    }
+}
+
+impl Write for ChildStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdin.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdin.flush()
+    }
+}
+
+/// Launches local worker processes which talk to us over stdin/stdout.
+///
+/// ```no_run
+/// use duty::process::ProcessPool;
+/// use duty::transport::Bincode;
+///
+/// let workers = ProcessPool::new("local_worker")
+///     .workers(4)
+///     .spawn(Bincode::new)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct ProcessPool {
+    program: OsString,
+    args: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
+    workers: usize,
+    restart: bool,
+}
+
+impl ProcessPool {
+    pub fn new(program: impl AsRef<OsStr>) -> ProcessPool {
+        ProcessPool {
+            program: program.as_ref().to_owned(),
+            args: Vec::new(),
+            envs: Vec::new(),
+            workers: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            restart: true,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> ProcessPool {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    pub fn args(mut self, args: impl IntoIterator<Item = impl AsRef<OsStr>>) -> ProcessPool {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> ProcessPool {
+        self.envs
+            .push((key.as_ref().to_owned(), value.as_ref().to_owned()));
+        self
+    }
+
+    /// Number of worker processes. Defaults to the number of CPUs.
+    pub fn workers(mut self, workers: usize) -> ProcessPool {
+        self.workers = workers;
+        self
+    }
+
+    /// Whether worker which exited is started again. Enabled by default.
+    pub fn restart(mut self, restart: bool) -> ProcessPool {
+        self.restart = restart;
+        self
+    }
+
+    /// Starts the workers, `make_transport` wraps the stream of each of them.
+    pub fn spawn<T, M>(self, make_transport: M) -> io::Result<Vec<Worker<T>>>
+    where
+        T: Transport,
+        M: Fn(ChildStream) -> T + Send + Sync + 'static,
+    {
+        let workers = self.workers;
+        let launcher = Arc::new(Launcher {
+            pool: self,
+            make_transport,
+        });
+
+        (0..workers)
+            .map(|index| {
+                let (child, transport) = launcher.launch(index)?;
+                Ok(Worker {
+                    index,
+                    launcher: launcher.clone(),
+                    child,
+                    transport,
+                    restarts: 0,
+                })
+            })
+            .collect()
+    }
+
+    /// Starts the workers and returns dispatcher calling all of them.
+    pub fn dispatcher<T, M>(self, make_transport: M) -> io::Result<Dispatcher<Worker<T>>>
+    where
+        T: Transport,
+        M: Fn(ChildStream) -> T + Send + Sync + 'static,
+    {
+        Ok(Dispatcher::new(self.spawn(make_transport)?))
+    }
+}
+
+struct Launcher<M> {
+    pool: ProcessPool,
+    make_transport: M,
+}
+
+trait Launch<T>: Send + Sync {
+    fn launch(&self, index: usize) -> io::Result<(Child, T)>;
+
+    fn restart_enabled(&self) -> bool;
+}
+
+impl<T, M> Launch<T> for Launcher<M>
+where
+    M: Fn(ChildStream) -> T + Send + Sync,
+{
+    fn launch(&self, index: usize) -> io::Result<(Child, T)> {
+        let mut child = Command::new(&self.pool.program)
+            .args(&self.pool.args)
+            .envs(self.pool.envs.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let pid = child.id();
+        tracing::debug!(worker = index, pid, "worker started");
+
+        let stderr = child.stderr.take().expect("Missing stderr");
+        std::thread::spawn(move || {
+            for line in BufReader::new(stderr).lines() {
+                match line {
+                    Ok(line) => tracing::info!(worker = index, pid, "{}", line),
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let stream = ChildStream {
+            stdout: child.stdout.take().expect("Missing stdout"),
+            stdin: child.stdin.take().expect("Missing stdin"),
+        };
+
+        Ok((child, (self.make_transport)(stream)))
+    }
+
+    fn restart_enabled(&self) -> bool {
+        self.pool.restart
+    }
+}
+
+/// Transport to a single worker process of [`ProcessPool`].
+///
+/// When the process exits it is started again before the next message is
+/// sent. Call which was in progress when the worker crashed fails.
+pub struct Worker<T> {
+    index: usize,
+    launcher: Arc<dyn Launch<T>>,
+    child: Child,
+    transport: T,
+    restarts: usize,
+}
+
+impl<T: Transport> Worker<T> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// How many times the worker process was restarted.
+    pub fn restarts(&self) -> usize {
+        self.restarts
+    }
+
+    /// Exit status of the worker process, if it has exited and wasn't
+    /// restarted yet.
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        self.child.try_wait()
+    }
+
+    fn restart_if_exited(&mut self) -> Result<(), Error> {
+        let status = match self.child.try_wait()? {
+            Some(status) => status,
+            None => return Ok(()),
+        };
+
+        tracing::warn!(worker = self.index, pid = self.child.id(), %status, "worker exited");
+
+        if !self.launcher.restart_enabled() {
+            return Ok(());
+        }
+
+        let (child, transport) = self.launcher.launch(self.index)?;
+
+        self.child = child;
+        self.transport = transport;
+        self.restarts += 1;
+
+        Ok(())
+    }
+}
+
+impl<T: Transport> Transport for Worker<T> {
+    fn receive<D: DeserializeOwned>(&mut self) -> Result<D, Error> {
+        self.transport.receive()
+    }
+
+    fn send<D: Serialize>(&mut self, data: &D) -> Result<(), Error> {
+        self.restart_if_exited()?;
+        self.transport.send(data)
+    }
+
+    fn call<B: Serialize, Out: DeserializeOwned + Send + 'static>(
+        &mut self,
+        request: &Envelope<B>,
+    ) -> Result<Out, Error> {
+        self.restart_if_exited()?;
+        self.transport.call(request)
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        self.transport.close()
+    }
+
+    fn byte_count(&self) -> ByteCount {
+        self.transport.byte_count()
+    }
+
+    fn peer_identity(&self) -> Option<&PeerIdentity> {
+        self.transport.peer_identity()
+    }
+
+    fn peer_addr(&self) -> Option<&str> {
+        self.transport.peer_addr()
+    }
+}
+
+impl<T> Drop for Worker<T> {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,256 @@
+use crate::envelope::{Envelope, ServiceRequest};
+use crate::error::Error;
+use crate::transport::{ByteCount, PeerIdentity, Transport};
+use serde::{de::DeserializeOwned, Serialize};
+use std::time::Duration;
+
+/// Transport which connects again when the connection is lost, e.g. because
+/// the server was restarted.
+///
+/// `connect` is called before the first message is sent and after each
+/// lost connection. Failed attempts are repeated with exponential backoff as
+/// long as the error is [retryable](Error::is_retryable). Calls whose
+/// connection was lost fail, unless they are marked as idempotent in their
+/// header (`#[duty(idempotent)]` methods of services), in which case they
+/// are sent again over the new connection.
+///
+/// ```no_run
+/// use duty::reconnect::Reconnect;
+/// use duty::transport::Bincode;
+/// use std::net::TcpStream;
+///
+/// let transport = Reconnect::new(|| Ok(Bincode::new(TcpStream::connect("127.0.0.1:4000")?)))
+///     .max_attempts(5);
+/// ```
+pub struct Reconnect<T, F> {
+    connect: F,
+    transport: Option<T>,
+    backoff: Backoff,
+    connections: usize,
+    /// Bytes transferred over connections which were already lost.
+    lost_bytes: ByteCount,
+}
+
+impl<T, F> Reconnect<T, F>
+where
+    T: Transport,
+    F: FnMut() -> Result<T, Error> + Send + 'static,
+{
+    pub fn new(connect: F) -> Reconnect<T, F> {
+        Reconnect {
+            connect,
+            transport: None,
+            backoff: Backoff::default(),
+            connections: 0,
+            lost_bytes: ByteCount::default(),
+        }
+    }
+
+    /// Delay after the first failed attempt, doubled after each next one.
+    /// Defaults to 50 ms.
+    pub fn initial_delay(mut self, delay: Duration) -> Reconnect<T, F> {
+        self.backoff.initial_delay = delay;
+        self
+    }
+
+    /// Limit of the delay between attempts. Defaults to 5 s.
+    pub fn max_delay(mut self, delay: Duration) -> Reconnect<T, F> {
+        self.backoff.max_delay = delay;
+        self
+    }
+
+    /// Number of attempts to connect before giving up. Defaults to 10.
+    /// Sending an idempotent call again shares the attempts with connecting
+    /// for it.
+    pub fn max_attempts(mut self, attempts: u32) -> Reconnect<T, F> {
+        self.backoff.max_attempts = attempts.max(1);
+        self
+    }
+
+    /// How many times the connection was established again after it was
+    /// lost.
+    pub fn reconnects(&self) -> usize {
+        self.connections.saturating_sub(1)
+    }
+
+    fn transport(&mut self) -> Result<&mut T, Error> {
+        self.transport_with(&mut 0)
+    }
+
+    /// Like [`Reconnect::transport`], but counts attempts to connect from
+    /// `attempt`, which is left at the last failed one.
+    fn transport_with(&mut self, attempt: &mut u32) -> Result<&mut T, Error> {
+        while self.transport.is_none() {
+            match (self.connect)() {
+                Ok(transport) => {
+                    self.transport = Some(transport);
+                    self.connections += 1;
+                }
+                Err(e) if e.is_retryable() && *attempt + 1 < self.backoff.max_attempts => {
+                    let delay = self.backoff.delay(*attempt);
+                    tracing::debug!(attempt = *attempt, ?delay, "connecting failed: {}", e);
+                    std::thread::sleep(delay);
+                    *attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(self.transport.as_mut().expect("connected"))
+    }
+
+    /// Drops the connection if `result` shows it was lost, so that the next
+    /// message is sent over a new one.
+    fn check<R>(&mut self, result: Result<R, Error>) -> Result<R, Error> {
+        if let Err(e) = &result {
+            if is_connection_lost(e) {
+                tracing::debug!("connection lost: {}", e);
+                if let Some(transport) = self.transport.take() {
+                    let bytes = transport.byte_count();
+                    self.lost_bytes.sent += bytes.sent;
+                    self.lost_bytes.received += bytes.received;
+                }
+            }
+        }
+
+        result
+    }
+}
+
+impl<T, F> Transport for Reconnect<T, F>
+where
+    T: Transport,
+    F: FnMut() -> Result<T, Error> + Send + 'static,
+{
+    fn receive<D: DeserializeOwned>(&mut self) -> Result<D, Error> {
+        let result = self.transport()?.receive();
+        self.check(result)
+    }
+
+    fn send<D: Serialize>(&mut self, data: &D) -> Result<(), Error> {
+        let result = self.transport()?.send(data);
+        self.check(result)
+    }
+
+    fn call<B: Serialize, Out: DeserializeOwned + Send + 'static>(
+        &mut self,
+        request: &Envelope<B>,
+    ) -> Result<Out, Error> {
+        // Shared by connecting and sending again
+        let mut attempt = 0;
+
+        loop {
+            let result = self.transport_with(&mut attempt)?.call(request);
+
+            match self.check(result) {
+                Err(e)
+                    if request.header.idempotent
+                        && is_connection_lost(&e)
+                        && attempt + 1 < self.backoff.max_attempts =>
+                {
+                    let delay = self.backoff.delay(attempt);
+                    tracing::debug!(
+                        request_id = request.header.request_id,
+                        attempt,
+                        ?delay,
+                        "sending {}::{} again",
+                        request.header.service,
+                        request.header.method
+                    );
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn send_owned<D: Serialize + Send + 'static>(&mut self, data: D) -> Result<(), Error> {
+        let result = self.transport()?.send_owned(data);
+        self.check(result)
+    }
+
+    fn receive_owned<D: DeserializeOwned + Send + 'static>(&mut self) -> Result<D, Error> {
+        let result = self.transport()?.receive_owned();
+        self.check(result)
+    }
+
+    fn receive_request<R>(&mut self) -> Result<Envelope<R>, Error>
+    where
+        R: ServiceRequest + DeserializeOwned + Send + 'static,
+    {
+        let result = self.transport()?.receive_request();
+        self.check(result)
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        match self.transport.as_mut() {
+            Some(transport) => transport.close(),
+            None => Ok(()),
+        }
+    }
+
+    fn byte_count(&self) -> ByteCount {
+        let current = self
+            .transport
+            .as_ref()
+            .map(T::byte_count)
+            .unwrap_or_default();
+
+        ByteCount {
+            sent: self.lost_bytes.sent + current.sent,
+            received: self.lost_bytes.received + current.received,
+        }
+    }
+
+    fn peer_identity(&self) -> Option<&PeerIdentity> {
+        self.transport.as_ref().and_then(T::peer_identity)
+    }
+
+    fn peer_addr(&self) -> Option<&str> {
+        self.transport.as_ref().and_then(T::peer_addr)
+    }
+}
+
+/// Whether the connection can't be used any more. HTTP statuses are
+/// retryable too, but leave the connection intact.
+fn is_connection_lost(error: &Error) -> bool {
+    error.is_retryable() && !matches!(error.root(), Error::Http { .. })
+}
+
+/// Delays between attempts.
+struct Backoff {
+    initial_delay: Duration,
+    max_delay: Duration,
+    max_attempts: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Backoff {
+        Backoff {
+            initial_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(5),
+            max_attempts: 10,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay after failed `attempt`, counted from 0. Random half of it is
+    /// left out, so that clients which lost connection to the same server
+    /// don't all come back at once.
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+
+        let mut random = [0; 4];
+        if getrandom::getrandom(&mut random).is_err() {
+            return delay;
+        }
+
+        let jitter = u32::from_ne_bytes(random) as f64 / u32::MAX as f64;
+        delay / 2 + (delay / 2).mul_f64(jitter)
+    }
+}
@@ -1,7 +1,12 @@
+use crate::auth::Authenticator;
+use crate::envelope::{Envelope, Header};
 use crate::error::Error;
+use crate::metrics::{Recorder, Side};
 use crate::procedure::Procedure;
-use crate::transport::Transport;
+use crate::trace::{self, TraceContext};
+use crate::transport::{PeerIdentity, Transport};
 use serde::{de::DeserializeOwned, Serialize};
+use tracing::Span;
 
 pub struct Server<T, R> {
     transport: T,
@@ -20,23 +25,65 @@ where
         }
     }
 
+    /// Verifies the client with `authenticator` before any request is handled.
+    pub fn with_auth<A: Authenticator>(
+        mut transport: T,
+        authenticator: &A,
+    ) -> Result<Server<T, R>, Error> {
+        authenticator.verify(&mut transport)?;
+        Ok(Server::new(transport))
+    }
+
     pub fn next<'s>(&'s mut self) -> Result<(R, RequestHandle<'s, T>), Error> {
-        let request = self.transport.receive()?;
+        let start_bytes = self.transport.byte_count();
+        let envelope: Envelope<R> = self.transport.receive_owned()?;
+        let recorder = Recorder::start(Side::Server, &envelope.header, start_bytes);
+        let context = envelope.header.trace.child();
+        let span = trace::server_span(&envelope.header, &context);
         let handle = RequestHandle {
             transport: &mut self.transport,
+            header: envelope.header,
+            context,
+            span,
+            recorder,
         };
-        Ok((request, handle))
+        Ok((envelope.body, handle))
     }
 }
 
 pub struct RequestHandle<'s, T> {
     transport: &'s mut T,
+    header: Header,
+    context: TraceContext,
+    span: Span,
+    recorder: Recorder,
 }
 
 impl<'s, T> RequestHandle<'s, T>
 where
     T: Transport,
 {
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Identity of the client established by the transport.
+    pub fn peer_identity(&self) -> Option<&PeerIdentity> {
+        self.transport.peer_identity()
+    }
+
+    /// Span of the request. Enter it while handling the request so that
+    /// events are attributed to the call.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Runs `f` inside the span and trace context of the request.
+    pub fn in_scope<F: FnOnce() -> O, O>(&self, f: F) -> O {
+        let _context_guard = self.context.enter();
+        self.span.in_scope(f)
+    }
+
     pub fn is_canceled(&self) -> bool {
         todo!();
     }
@@ -46,6 +93,7 @@ where
         _proc: &Proc,
         response: &Proc::Response,
     ) -> Result<(), Error> {
-        self.transport.send(response)
+        let _span_guard = self.span.enter();
+        self.recorder.respond(self.transport, response)
     }
 }
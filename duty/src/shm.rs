@@ -0,0 +1,344 @@
+use memmap2::MmapRaw;
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::FromRawFd;
+use std::path::Path;
+use std::sync::atomic::{fence, AtomicU32, Ordering};
+
+const MAGIC: u32 = 0x6475_7479;
+
+/// Shared header is followed by the data of both rings.
+const HEADER_LEN: usize = 4096;
+
+const MIN_CAPACITY: usize = 4096;
+const MAX_CAPACITY: usize = 1 << 30;
+
+#[repr(C)]
+struct Header {
+    magic: AtomicU32,
+    capacity: AtomicU32,
+    attached: AtomicU32,
+    rings: [Ring; 2],
+}
+
+/// Single producer, single consumer ring. Positions grow without bounds
+/// (wrapping at `u32::MAX`) and are taken modulo capacity when indexing
+/// the data, so that full and empty ring can be told apart.
+#[repr(C, align(64))]
+struct Ring {
+    head: AtomicU32,
+    tail: AtomicU32,
+    closed: AtomicU32,
+    /// Number of threads sleeping on `head` or `tail`, so that the other
+    /// side makes the wake syscall only when needed.
+    waiters: AtomicU32,
+}
+
+impl Ring {
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire) != 0
+    }
+
+    /// Sleeps until `word` changes from `observed` or the ring is closed.
+    fn wait(&self, word: &AtomicU32, observed: u32) {
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        if word.load(Ordering::SeqCst) == observed && !self.is_closed() {
+            futex_wait(word, observed);
+        }
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn wake(&self, word: &AtomicU32) {
+        // Pairs with the increment of `waiters` in `wait`, either the waiter
+        // sees the new value of `word` or we see the waiter
+        fence(Ordering::SeqCst);
+        if self.waiters.load(Ordering::SeqCst) != 0 {
+            futex_wake(word);
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(1, Ordering::SeqCst);
+        futex_wake(&self.head);
+        futex_wake(&self.tail);
+    }
+}
+
+/// Duplex byte stream over a shared memory mapping, for workers on the same
+/// host exchanging large messages.
+///
+/// The mapping holds one ring buffer for each direction, so data is copied
+/// only into and out of the shared memory, never through the kernel.
+/// Blocked reader or writer sleeps on a futex until the other side makes
+/// progress. Dropping either side ends the stream: the peer reads the
+/// remaining data followed by EOF and its writes fail with
+/// [`io::ErrorKind::BrokenPipe`]. A peer which dies without unmapping
+/// (e.g. killed process) is not detected.
+///
+/// Side which creates the mapping is connected to the side which opens it,
+/// e.g. `ShmStream::create(path, ...)` in the driver and
+/// `ShmStream::open(path)` in the worker. Plugs into other transports as any
+/// stream, e.g. `Bincode::new(ShmStream::open(path)?)`.
+pub struct ShmStream {
+    mmap: MmapRaw,
+    capacity: usize,
+    /// Index of the ring this side writes to, the other one is read.
+    side: usize,
+}
+
+impl ShmStream {
+    /// Creates file at `path` (e.g. in `/dev/shm`) with rings of at least
+    /// `capacity` bytes. The file is not removed when the stream is dropped.
+    pub fn create(path: impl AsRef<Path>, capacity: usize) -> io::Result<ShmStream> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+
+        ShmStream::init(&file, capacity)
+    }
+
+    /// Connects to the stream created at `path`.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<ShmStream> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        ShmStream::attach(&file)
+    }
+
+    /// Creates anonymous memory file named `name` (visible only in
+    /// `/proc/<pid>/fd`). The returned file is inherited by child processes,
+    /// which connect to the stream with [`ShmStream::from_file`].
+    pub fn memfd(name: &str, capacity: usize) -> io::Result<(ShmStream, File)> {
+        let name =
+            CString::new(name).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        // SAFETY: `name` is a valid NUL-terminated string
+        let fd = unsafe { libc::memfd_create(name.as_ptr(), 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: `fd` was just created and is owned by nothing else
+        let file = unsafe { File::from_raw_fd(fd) };
+        let stream = ShmStream::init(&file, capacity)?;
+        Ok((stream, file))
+    }
+
+    /// Connects to the stream created with [`ShmStream::memfd`], e.g. with
+    /// file descriptor number passed to a child process.
+    pub fn from_file(file: &File) -> io::Result<ShmStream> {
+        ShmStream::attach(file)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn init(file: &File, capacity: usize) -> io::Result<ShmStream> {
+        let capacity = capacity
+            .clamp(MIN_CAPACITY, MAX_CAPACITY)
+            .next_power_of_two();
+        file.set_len((HEADER_LEN + 2 * capacity) as u64)?;
+
+        let stream = ShmStream {
+            mmap: MmapRaw::map_raw(file)?,
+            capacity,
+            side: 0,
+        };
+
+        let header = stream.header();
+        header.capacity.store(capacity as u32, Ordering::Relaxed);
+        header.magic.store(MAGIC, Ordering::Release);
+
+        Ok(stream)
+    }
+
+    fn attach(file: &File) -> io::Result<ShmStream> {
+        let mmap = MmapRaw::map_raw(file)?;
+        if mmap.len() < HEADER_LEN {
+            return Err(invalid_data("shared memory file is too small"));
+        }
+
+        // Stream is constructed only when attaching succeeds, as dropping it
+        // closes the rings
+        let header = header(&mmap);
+        if header.magic.load(Ordering::Acquire) != MAGIC {
+            return Err(invalid_data("shared memory file is not initialized"));
+        }
+
+        // Indexing relies on positions wrapping at a multiple of capacity
+        let capacity = header.capacity.load(Ordering::Relaxed) as usize;
+        if !(MIN_CAPACITY..=MAX_CAPACITY).contains(&capacity) || !capacity.is_power_of_two() {
+            return Err(invalid_data("shared memory file has invalid capacity"));
+        }
+        if mmap.len() < HEADER_LEN + 2 * capacity {
+            return Err(invalid_data("shared memory file is truncated"));
+        }
+
+        if header.attached.swap(1, Ordering::AcqRel) != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "shared memory stream is already connected",
+            ));
+        }
+
+        Ok(ShmStream {
+            mmap,
+            capacity,
+            side: 1,
+        })
+    }
+
+    fn header(&self) -> &Header {
+        header(&self.mmap)
+    }
+
+    fn ring(&self, index: usize) -> &Ring {
+        &self.header().rings[index]
+    }
+
+    fn data(&self, index: usize) -> *mut u8 {
+        // SAFETY: the mapping holds the header and both rings of `capacity`
+        // bytes, checked when attaching, and `index` is 0 or 1
+        unsafe {
+            self.mmap
+                .as_mut_ptr()
+                .add(HEADER_LEN + index * self.capacity)
+        }
+    }
+}
+
+impl Read for ShmStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let index = 1 - self.side;
+        let ring = self.ring(index);
+        let tail = ring.tail.load(Ordering::Relaxed);
+
+        let head = loop {
+            let closed = ring.is_closed();
+            let head = ring.head.load(Ordering::Acquire);
+            if head != tail {
+                break head;
+            }
+            if closed {
+                return Ok(0);
+            }
+            ring.wait(&ring.head, head);
+        };
+
+        let len = buf.len().min(head.wrapping_sub(tail) as usize);
+        let start = tail as usize % self.capacity;
+        let first = len.min(self.capacity - start);
+
+        // SAFETY: `start + first` and `len - first` are within the ring and
+        // `len` within `buf`. The peer doesn't write the `len` bytes after
+        // the tail until it is advanced below.
+        unsafe {
+            let data = self.data(index);
+            std::ptr::copy_nonoverlapping(data.add(start), buf.as_mut_ptr(), first);
+            std::ptr::copy_nonoverlapping(data, buf.as_mut_ptr().add(first), len - first);
+        }
+
+        ring.tail
+            .store(tail.wrapping_add(len as u32), Ordering::Release);
+        ring.wake(&ring.tail);
+
+        Ok(len)
+    }
+}
+
+impl Write for ShmStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let index = self.side;
+        let ring = self.ring(index);
+        let head = ring.head.load(Ordering::Relaxed);
+
+        let free = loop {
+            if ring.is_closed() {
+                return Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "shared memory stream closed by peer",
+                ));
+            }
+            let tail = ring.tail.load(Ordering::Acquire);
+            let free = self.capacity - head.wrapping_sub(tail) as usize;
+            if free > 0 {
+                break free;
+            }
+            ring.wait(&ring.tail, tail);
+        };
+
+        let len = buf.len().min(free);
+        let start = head as usize % self.capacity;
+        let first = len.min(self.capacity - start);
+
+        // SAFETY: `start + first` and `len - first` are within the ring and
+        // `len` within `buf`. The peer doesn't read the `len` free bytes
+        // after the head until it is advanced below.
+        unsafe {
+            let data = self.data(index);
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), data.add(start), first);
+            std::ptr::copy_nonoverlapping(buf.as_ptr().add(first), data, len - first);
+        }
+
+        ring.head
+            .store(head.wrapping_add(len as u32), Ordering::Release);
+        ring.wake(&ring.head);
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ShmStream {
+    fn drop(&mut self) {
+        self.ring(0).close();
+        self.ring(1).close();
+    }
+}
+
+fn header(mmap: &MmapRaw) -> &Header {
+    // SAFETY: the mapping is page aligned and at least HEADER_LEN long,
+    // which holds the header. It is shared with the peer, so it is accessed
+    // only through atomics.
+    unsafe { &*(mmap.as_mut_ptr() as *const Header) }
+}
+
+fn futex_wait(word: &AtomicU32, observed: u32) {
+    // Spurious wake-ups and EAGAIN (value already changed) are handled by
+    // callers re-checking their condition
+    // SAFETY: `word` is a valid aligned u32 for the duration of the call
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAIT,
+            observed,
+            std::ptr::null::<libc::timespec>(),
+        );
+    }
+}
+
+fn futex_wake(word: &AtomicU32) {
+    // SAFETY: `word` is a valid aligned u32 for the duration of the call
+    unsafe {
+        libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAKE, i32::MAX);
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
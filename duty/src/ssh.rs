@@ -0,0 +1,381 @@
+use crate::dispatcher::Dispatcher;
+use crate::transport::{PeerAddr, Transport};
+use ssh2::{Channel, CheckResult, HashType, KnownHostFileKind, Session};
+use std::io::{self, Read, Write};
+use std::net::{Ipv6Addr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Authentication method. Methods are tried in the order they were added to
+/// the builder until one of them succeeds.
+#[derive(Clone)]
+pub enum Auth {
+    Agent,
+    KeyFile {
+        private_key: PathBuf,
+        public_key: Option<PathBuf>,
+        passphrase: Option<String>,
+    },
+    Password(String),
+}
+
+impl std::fmt::Debug for Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Auth::Agent => f.write_str("Agent"),
+            Auth::KeyFile {
+                private_key,
+                public_key,
+                ..
+            } => f
+                .debug_struct("KeyFile")
+                .field("private_key", private_key)
+                .field("public_key", public_key)
+                .finish_non_exhaustive(),
+            Auth::Password(_) => f.write_str("Password(..)"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum HostKeyCheck {
+    /// Host key must be listed in the given OpenSSH `known_hosts` file.
+    KnownHosts(PathBuf),
+    /// SHA-256 hash of the host key must match.
+    Sha256(Vec<u8>),
+    /// Any host key is accepted. Use only on trusted networks.
+    AcceptAny,
+}
+
+/// Builder of [`SshSession`]. The same builder can be used to connect to
+/// many hosts.
+#[derive(Clone, Debug)]
+pub struct SshSessionBuilder {
+    user: Option<String>,
+    auth: Vec<Auth>,
+    host_key_check: Option<HostKeyCheck>,
+    keepalive: Option<Duration>,
+    timeout: Option<Duration>,
+}
+
+impl SshSessionBuilder {
+    /// User name. Defaults to the `USER` environment variable.
+    pub fn user(mut self, user: impl Into<String>) -> SshSessionBuilder {
+        self.user = Some(user.into());
+        self
+    }
+
+    /// Adds authentication method. SSH agent is used if none is added.
+    pub fn auth(mut self, auth: Auth) -> SshSessionBuilder {
+        self.auth.push(auth);
+        self
+    }
+
+    /// Defaults to `~/.ssh/known_hosts`.
+    pub fn host_key_check(mut self, check: HostKeyCheck) -> SshSessionBuilder {
+        self.host_key_check = Some(check);
+        self
+    }
+
+    /// Interval of keepalive messages, sent while a stream of the session
+    /// waits for data of the remote command.
+    pub fn keepalive(mut self, interval: Duration) -> SshSessionBuilder {
+        self.keepalive = Some(interval);
+        self
+    }
+
+    /// Timeout of blocking session operations.
+    pub fn timeout(mut self, timeout: Duration) -> SshSessionBuilder {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Connects to `host`, which is either `name` or `name:port`. IPv6
+    /// addresses with port are enclosed in brackets, e.g. `[::1]:22`.
+    pub fn connect(&self, host: &str) -> io::Result<SshSession> {
+        let (name, port) = parse_host(host)?;
+
+        let tcp = TcpStream::connect((name, port))?;
+        let peer_addr = PeerAddr::peer_addr(&tcp);
+
+        let mut session = Session::new()?;
+        if let Some(timeout) = self.timeout {
+            session.set_timeout(timeout.as_millis().try_into().unwrap_or(u32::MAX));
+        }
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+
+        self.check_host_key(&session, name, port)?;
+        self.authenticate(&session)?;
+
+        if let Some(interval) = self.keepalive {
+            session.set_keepalive(
+                false,
+                interval.as_secs().max(1).try_into().unwrap_or(u32::MAX),
+            );
+        }
+
+        Ok(SshSession {
+            session,
+            keepalive: self.keepalive,
+            peer_addr,
+        })
+    }
+
+    /// Runs `command` on every host and returns dispatcher calling all of
+    /// them. The command is expected to serve requests on its stdin/stdout.
+    /// Transports made with e.g. [`Framed::from_stream`] report the host in
+    /// errors of calls.
+    ///
+    /// [`Framed::from_stream`]: crate::transport::Framed::from_stream
+    pub fn launch<T, M>(
+        &self,
+        hosts: impl IntoIterator<Item = impl AsRef<str>>,
+        command: &str,
+        make_transport: M,
+    ) -> io::Result<Dispatcher<T>>
+    where
+        T: Transport,
+        M: Fn(SshStream) -> T,
+    {
+        let transports = hosts
+            .into_iter()
+            .map(|host| {
+                let stream = self.connect(host.as_ref())?.exec(command)?;
+                Ok(make_transport(stream))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(Dispatcher::new(transports))
+    }
+
+    fn check_host_key(&self, session: &Session, name: &str, port: u16) -> io::Result<()> {
+        let check = match &self.host_key_check {
+            Some(check) => check.clone(),
+            None => HostKeyCheck::KnownHosts(default_known_hosts()?),
+        };
+
+        let (key, _) = session
+            .host_key()
+            .ok_or_else(|| host_key_error("server did not send host key"))?;
+
+        match check {
+            HostKeyCheck::KnownHosts(path) => {
+                let mut known_hosts = session.known_hosts()?;
+                known_hosts.read_file(&path, KnownHostFileKind::OpenSSH)?;
+                match known_hosts.check_port(name, port, key) {
+                    CheckResult::Match => Ok(()),
+                    CheckResult::Mismatch => Err(host_key_error("host key mismatch")),
+                    CheckResult::NotFound => Err(host_key_error("host is not known")),
+                    CheckResult::Failure => Err(host_key_error("cannot check host key")),
+                }
+            }
+            HostKeyCheck::Sha256(expected) => match session.host_key_hash(HashType::Sha256) {
+                Some(hash) if hash == expected.as_slice() => Ok(()),
+                _ => Err(host_key_error("host key mismatch")),
+            },
+            HostKeyCheck::AcceptAny => Ok(()),
+        }
+    }
+
+    fn authenticate(&self, session: &Session) -> io::Result<()> {
+        let user = match &self.user {
+            Some(user) => user.clone(),
+            None => std::env::var("USER").map_err(invalid_input)?,
+        };
+
+        let default_auth = [Auth::Agent];
+        let methods = if self.auth.is_empty() {
+            &default_auth[..]
+        } else {
+            &self.auth[..]
+        };
+
+        let mut last_error = None;
+        for method in methods {
+            let result = match method {
+                Auth::Agent => session.userauth_agent(&user),
+                Auth::KeyFile {
+                    private_key,
+                    public_key,
+                    passphrase,
+                } => session.userauth_pubkey_file(
+                    &user,
+                    public_key.as_deref(),
+                    private_key,
+                    passphrase.as_deref(),
+                ),
+                Auth::Password(password) => session.userauth_password(&user, password),
+            };
+
+            match result {
+                Ok(()) if session.authenticated() => return Ok(()),
+                Ok(()) => {}
+                Err(e) => {
+                    tracing::debug!("ssh authentication failed: {}", e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(match last_error {
+            Some(e) => io::Error::new(io::ErrorKind::PermissionDenied, e),
+            None => io::Error::new(io::ErrorKind::PermissionDenied, "authentication failed"),
+        })
+    }
+}
+
+/// Authenticated SSH session.
+pub struct SshSession {
+    session: Session,
+    keepalive: Option<Duration>,
+    peer_addr: Option<String>,
+}
+
+impl SshSession {
+    pub fn builder() -> SshSessionBuilder {
+        SshSessionBuilder {
+            user: None,
+            auth: Vec::new(),
+            host_key_check: None,
+            keepalive: None,
+            timeout: None,
+        }
+    }
+
+    /// Executes `command` on the remote host. Returned stream is connected to
+    /// its stdin and stdout.
+    pub fn exec(&self, command: &str) -> io::Result<SshStream> {
+        let mut channel = self.session.channel_session()?;
+        channel.exec(command)?;
+
+        Ok(SshStream {
+            channel,
+            session: self.session.clone(),
+            keepalive: self.keepalive,
+            peer_addr: self.peer_addr.clone(),
+        })
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+}
+
+/// Stdin and stdout of a remote command.
+pub struct SshStream {
+    channel: Channel,
+    session: Session,
+    keepalive: Option<Duration>,
+    peer_addr: Option<String>,
+}
+
+impl SshStream {
+    pub fn channel(&mut self) -> &mut Channel {
+        &mut self.channel
+    }
+}
+
+impl Read for SshStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some(interval) = self.keepalive else {
+            return self.channel.read(buf);
+        };
+
+        // Calls on the session are serialized, so keepalives can't be sent
+        // from another thread while this read blocks. Waits for data at most
+        // one interval at a time and sends them in between instead.
+        let timeout = self.session.timeout();
+        let deadline =
+            (timeout > 0).then(|| Instant::now() + Duration::from_millis(timeout.into()));
+
+        let result = loop {
+            if let Err(e) = self.session.keepalive_send() {
+                break Err(e.into());
+            }
+
+            let wait = match deadline {
+                Some(deadline) => interval.min(deadline.saturating_duration_since(Instant::now())),
+                None => interval,
+            };
+            self.session.set_timeout(millis(wait));
+
+            match self.channel.read(buf) {
+                Err(e)
+                    if e.kind() == io::ErrorKind::TimedOut
+                        && deadline.is_none_or(|deadline| Instant::now() < deadline) => {}
+                result => break result,
+            }
+        };
+
+        self.session.set_timeout(timeout);
+        result
+    }
+}
+
+/// Address of the SSH server the command runs on.
+impl PeerAddr for SshStream {
+    fn peer_addr(&self) -> Option<String> {
+        self.peer_addr.clone()
+    }
+}
+
+impl Write for SshStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.channel.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.channel.flush()
+    }
+}
+
+/// Timeout of session operations in milliseconds, at least 1 as 0 means
+/// none.
+fn millis(duration: Duration) -> u32 {
+    duration.as_millis().clamp(1, u32::MAX.into()) as u32
+}
+
+/// Splits `host` into name and port, which defaults to 22.
+fn parse_host(host: &str) -> io::Result<(&str, u16)> {
+    if let Some(rest) = host.strip_prefix('[') {
+        let (name, rest) = rest
+            .split_once(']')
+            .ok_or_else(|| invalid_input("missing `]` in host"))?;
+        let port = match rest {
+            "" => 22,
+            _ => rest
+                .strip_prefix(':')
+                .ok_or_else(|| invalid_input("expected port after `]`"))?
+                .parse()
+                .map_err(invalid_input)?,
+        };
+        return Ok((name, port));
+    }
+
+    // IPv6 address without port
+    if host.parse::<Ipv6Addr>().is_ok() {
+        return Ok((host, 22));
+    }
+
+    match host.rsplit_once(':') {
+        Some((name, port)) => Ok((name, port.parse().map_err(invalid_input)?)),
+        None => Ok((host, 22)),
+    }
+}
+
+fn default_known_hosts() -> io::Result<PathBuf> {
+    let home = std::env::var_os("HOME").ok_or_else(|| invalid_input("HOME is not set"))?;
+    Ok(Path::new(&home).join(".ssh").join("known_hosts"))
+}
+
+fn host_key_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, message.to_owned())
+}
+
+fn invalid_input<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidInput, e)
+}
@@ -1,62 +1,151 @@
+use std::collections::VecDeque;
 use std::io::{self, Read, Write};
 use std::io::{Stdin, Stdout};
-use std::sync::mpsc::{channel, Receiver, RecvError, SendError, Sender};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 
+/// Capacity of each direction of [`MpscStream::new_pair`].
+pub const DEFAULT_CAPACITY: usize = 64 * 1024;
+
+/// In-process duplex stream, e.g. for connecting client and server running
+/// in different threads.
+///
+/// Each direction is a bounded buffer: writes block while it is full, reads
+/// block while it is empty and return whatever is available. Closing the
+/// writing half (explicitly or by dropping the stream) lets the peer read
+/// the remaining data followed by EOF, writes to a dropped peer fail with
+/// [`io::ErrorKind::BrokenPipe`].
 pub struct MpscStream {
-    sender: Sender<u8>,
-    receiver: Receiver<u8>,
+    sender: Arc<Pipe>,
+    receiver: Arc<Pipe>,
+}
+
+struct Pipe {
+    state: Mutex<PipeState>,
+    readable: Condvar,
+    writable: Condvar,
+    capacity: usize,
+}
+
+struct PipeState {
+    buffer: VecDeque<u8>,
+    writer_closed: bool,
+    reader_closed: bool,
+}
+
+impl Pipe {
+    fn new(capacity: usize) -> Arc<Pipe> {
+        Arc::new(Pipe {
+            state: Mutex::new(PipeState {
+                buffer: VecDeque::new(),
+                writer_closed: false,
+                reader_closed: false,
+            }),
+            readable: Condvar::new(),
+            writable: Condvar::new(),
+            capacity,
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PipeState> {
+        // State is consistent after every operation, so it is safe to use
+        // even if other side panicked
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn close_writer(&self) {
+        self.lock().writer_closed = true;
+        self.readable.notify_all();
+    }
+
+    fn close_reader(&self) {
+        let mut state = self.lock();
+        state.reader_closed = true;
+        state.buffer.clear();
+        drop(state);
+        self.writable.notify_all();
+    }
 }
 
 impl MpscStream {
     pub fn new_pair() -> (MpscStream, MpscStream) {
-        let (send1, recv1) = channel();
-        let (send2, recv2) = channel();
+        MpscStream::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Like [`MpscStream::new_pair`], but each direction buffers at most
+    /// `capacity` bytes.
+    pub fn with_capacity(capacity: usize) -> (MpscStream, MpscStream) {
+        let capacity = capacity.max(1);
+        let first = Pipe::new(capacity);
+        let second = Pipe::new(capacity);
         (
             MpscStream {
-                sender: send1,
-                receiver: recv2,
+                sender: first.clone(),
+                receiver: second.clone(),
             },
             MpscStream {
-                sender: send2,
-                receiver: recv1,
+                sender: second,
+                receiver: first,
             },
         )
     }
+
+    /// Closes writing half of the stream, the peer reads EOF once it
+    /// consumes the data written so far.
+    pub fn close_write(&mut self) {
+        self.sender.close_writer();
+    }
 }
 
 impl Read for MpscStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        for b in buf.iter_mut() {
-            match self.receiver.recv() {
-                Ok(data) => *b = data,
-                Err(RecvError) => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        "Cannot receive data: channel disconnected",
-                    ))
-                }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let pipe = &self.receiver;
+        let mut state = pipe.lock();
+        while state.buffer.is_empty() {
+            if state.writer_closed {
+                return Ok(0);
             }
+            state = pipe.readable.wait(state).unwrap_or_else(|e| e.into_inner());
         }
 
-        Ok(buf.len())
+        let len = state.buffer.read(buf)?;
+        drop(state);
+        pipe.writable.notify_one();
+
+        Ok(len)
     }
 }
 
 impl Write for MpscStream {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        for b in buf.iter() {
-            match self.sender.send(*b) {
-                Ok(()) => {}
-                Err(SendError(_)) => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        "Cannot send data: channel disconnected",
-                    ))
-                }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let pipe = &self.sender;
+        let mut state = pipe.lock();
+        loop {
+            if state.reader_closed || state.writer_closed {
+                return Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "Cannot send data: stream closed",
+                ));
+            }
+            if state.buffer.len() < pipe.capacity {
+                break;
             }
+            state = pipe.writable.wait(state).unwrap_or_else(|e| e.into_inner());
         }
 
-        Ok(buf.len())
+        let len = buf.len().min(pipe.capacity - state.buffer.len());
+        state.buffer.extend(&buf[..len]);
+        drop(state);
+        pipe.readable.notify_one();
+
+        Ok(len)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -64,11 +153,24 @@ impl Write for MpscStream {
     }
 }
 
+impl Drop for MpscStream {
+    fn drop(&mut self) {
+        self.sender.close_writer();
+        self.receiver.close_reader();
+    }
+}
+
 pub struct Stdinout {
     stdin: Stdin,
     stdout: Stdout,
 }
 
+impl Default for Stdinout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Stdinout {
     pub fn new() -> Stdinout {
         Stdinout {
@@ -0,0 +1,187 @@
+use crate::transport::{Framed, PeerAddr, PeerIdentity};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{
+    ClientConfig, ClientConnection, CommonState, ConnectionCommon, RootCertStore, ServerConfig,
+    ServerConnection, StreamOwned,
+};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::Arc;
+
+pub type TlsServerStream<S> = StreamOwned<ServerConnection, S>;
+pub type TlsClientStream<S> = StreamOwned<ClientConnection, S>;
+
+/// Loads all certificates from a PEM file.
+pub fn load_certs(path: impl AsRef<Path>) -> io::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::certs(&mut reader).collect()
+}
+
+/// Loads the first private key from a PEM file.
+pub fn load_private_key(path: impl AsRef<Path>) -> io::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(File::open(path.as_ref())?);
+    rustls_pemfile::private_key(&mut reader)?.ok_or_else(|| {
+        invalid_data(format!(
+            "no private key found in {}",
+            path.as_ref().display()
+        ))
+    })
+}
+
+pub fn root_store(
+    certs: impl IntoIterator<Item = CertificateDer<'static>>,
+) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in certs {
+        roots.add(cert).map_err(invalid_data)?;
+    }
+    Ok(roots)
+}
+
+/// Server side of TLS connections.
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    config: Arc<ServerConfig>,
+}
+
+impl TlsAcceptor {
+    /// Accepts any client.
+    pub fn new(
+        cert_chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> io::Result<TlsAcceptor> {
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(cert_chain, key)
+            .map_err(invalid_data)?;
+
+        Ok(TlsAcceptor::from_config(Arc::new(config)))
+    }
+
+    /// Accepts only clients presenting certificate signed by one of `client_roots`.
+    pub fn with_client_auth(
+        cert_chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+        client_roots: RootCertStore,
+    ) -> io::Result<TlsAcceptor> {
+        let verifier = WebPkiClientVerifier::builder(Arc::new(client_roots))
+            .build()
+            .map_err(invalid_data)?;
+
+        let config = ServerConfig::builder()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(cert_chain, key)
+            .map_err(invalid_data)?;
+
+        Ok(TlsAcceptor::from_config(Arc::new(config)))
+    }
+
+    pub fn from_config(config: Arc<ServerConfig>) -> TlsAcceptor {
+        TlsAcceptor { config }
+    }
+
+    /// Performs TLS handshake on `stream`.
+    pub fn accept<S: Read + Write>(&self, mut stream: S) -> io::Result<TlsServerStream<S>> {
+        let mut conn = ServerConnection::new(self.config.clone()).map_err(invalid_data)?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)?;
+        }
+        Ok(StreamOwned::new(conn, stream))
+    }
+}
+
+/// Client side of TLS connections.
+#[derive(Clone)]
+pub struct TlsConnector {
+    config: Arc<ClientConfig>,
+}
+
+impl TlsConnector {
+    /// Trusts servers with certificate signed by one of `roots`.
+    pub fn new(roots: RootCertStore) -> TlsConnector {
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        TlsConnector::from_config(Arc::new(config))
+    }
+
+    /// Like [`TlsConnector::new`], but also presents client certificate.
+    pub fn with_client_auth(
+        roots: RootCertStore,
+        cert_chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> io::Result<TlsConnector> {
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(cert_chain, key)
+            .map_err(invalid_data)?;
+
+        Ok(TlsConnector::from_config(Arc::new(config)))
+    }
+
+    pub fn from_config(config: Arc<ClientConfig>) -> TlsConnector {
+        TlsConnector { config }
+    }
+
+    /// Performs TLS handshake on `stream`. Server certificate must be valid
+    /// for `server_name`.
+    pub fn connect<S: Read + Write>(
+        &self,
+        server_name: &str,
+        mut stream: S,
+    ) -> io::Result<TlsClientStream<S>> {
+        let server_name = ServerName::try_from(server_name.to_owned()).map_err(invalid_input)?;
+        let mut conn =
+            ClientConnection::new(self.config.clone(), server_name).map_err(invalid_data)?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)?;
+        }
+        Ok(StreamOwned::new(conn, stream))
+    }
+}
+
+impl<C, Conn, Data, S> Framed<C, StreamOwned<Conn, S>>
+where
+    Conn: DerefMut + Deref<Target = ConnectionCommon<Data>>,
+    S: Read + Write + PeerAddr,
+{
+    /// Transport over established TLS connection of either side, which
+    /// reports certificate of the peer as its identity and address of the
+    /// underlying stream as its address.
+    pub fn from_tls(stream: StreamOwned<Conn, S>) -> Framed<C, StreamOwned<Conn, S>> {
+        let identity = peer_identity(&stream.conn);
+        Framed::from_stream(stream).with_peer_identity(identity)
+    }
+}
+
+impl<Conn, S: Read + Write + PeerAddr> PeerAddr for StreamOwned<Conn, S> {
+    fn peer_addr(&self) -> Option<String> {
+        self.sock.peer_addr()
+    }
+}
+
+/// Identity of the peer of an established connection, e.g.
+/// `tls::peer_identity(&stream.conn)`.
+pub fn peer_identity(conn: &CommonState) -> Option<PeerIdentity> {
+    conn.peer_certificates().map(|certs| PeerIdentity {
+        certificates: certs.iter().map(|cert| cert.to_vec()).collect(),
+    })
+}
+
+fn invalid_data<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn invalid_input<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidInput, e)
+}
@@ -0,0 +1,115 @@
+use crate::envelope::Header;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use tracing::Span;
+
+/// Identifies a call within a distributed trace.
+///
+/// `trace_id` is shared by all calls made on behalf of the same top-level
+/// call, `span_id` identifies the calling span. Both are recorded on client
+/// and server spans so that worker logs can be joined with the driver's ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceContext {
+    pub trace_id: u64,
+    pub span_id: u64,
+}
+
+thread_local! {
+    static CURRENT: Cell<Option<TraceContext>> = const { Cell::new(None) };
+}
+
+impl TraceContext {
+    /// Starts a new trace.
+    pub fn new_root() -> TraceContext {
+        TraceContext {
+            trace_id: next_id(),
+            span_id: next_id(),
+        }
+    }
+
+    /// Creates context for a new span. It belongs to the trace of the current
+    /// thread or starts a new one if there is none.
+    pub fn new_child() -> TraceContext {
+        match TraceContext::current() {
+            Some(current) => current.child(),
+            None => TraceContext::new_root(),
+        }
+    }
+
+    /// Trace context of the call currently handled by this thread.
+    pub fn current() -> Option<TraceContext> {
+        CURRENT.with(Cell::get)
+    }
+
+    /// Creates context for a span nested in this one.
+    pub fn child(&self) -> TraceContext {
+        TraceContext {
+            trace_id: self.trace_id,
+            span_id: next_id(),
+        }
+    }
+
+    /// Makes this context current for this thread until the guard is dropped.
+    pub fn enter(self) -> ContextGuard {
+        let previous = CURRENT.with(|current| current.replace(Some(self)));
+        ContextGuard { previous }
+    }
+}
+
+pub struct ContextGuard {
+    previous: Option<TraceContext>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.previous));
+    }
+}
+
+/// Creates span for an outgoing call.
+pub fn client_span(header: &Header) -> Span {
+    tracing::info_span!(
+        "duty.call",
+        service = %header.service,
+        method = %header.method,
+        request_id = header.request_id,
+        trace_id = %format_args!("{:016x}", header.trace.trace_id),
+        span_id = %format_args!("{:016x}", header.trace.span_id),
+    )
+}
+
+/// Creates span for an incoming call.
+pub fn server_span(header: &Header, context: &TraceContext) -> Span {
+    tracing::info_span!(
+        "duty.dispatch",
+        service = %header.service,
+        method = %header.method,
+        request_id = header.request_id,
+        trace_id = %format_args!("{:016x}", context.trace_id),
+        span_id = %format_args!("{:016x}", context.span_id),
+        parent_span_id = %format_args!("{:016x}", header.trace.span_id),
+    )
+}
+
+/// Runs `f` inside the server span of a request. Calls made by `f` join the
+/// trace of the request.
+pub fn dispatch<R>(header: &Header, f: impl FnOnce() -> R) -> R {
+    let context = header.trace.child();
+    let span = server_span(header, &context);
+    let _span_guard = span.enter();
+    let _context_guard = context.enter();
+    f()
+}
+
+fn next_id() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    static SEED: OnceLock<RandomState> = OnceLock::new();
+
+    let mut hasher = SEED.get_or_init(RandomState::new).build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
@@ -1,6 +1,8 @@
+use crate::envelope::{Envelope, ServiceRequest};
 use crate::error::Error;
 use serde::{de::DeserializeOwned, Serialize};
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
 
 pub trait Transport: Send + 'static {
     fn receive<T: DeserializeOwned>(&mut self) -> Result<T, Error>;
@@ -14,47 +16,407 @@ pub trait Transport: Send + 'static {
         self.send(input)?;
         self.receive()
     }
+
+    /// Sends request and waits for its response. Transports routing requests
+    /// by their service or method (e.g. HTTP) override it to read the header.
+    fn call<B: Serialize, Out: DeserializeOwned + Send + 'static>(
+        &mut self,
+        request: &Envelope<B>,
+    ) -> Result<Out, Error> {
+        self.send_receive(request)
+    }
+
+    /// Like [`Transport::send`], but takes the message by value. In-process
+    /// transports override it to pass the value without serializing it.
+    fn send_owned<T: Serialize + Send + 'static>(&mut self, data: T) -> Result<(), Error> {
+        self.send(&data)
+    }
+
+    /// Like [`Transport::receive`], but accepts also values passed by
+    /// [`Transport::send_owned`] of in-process transports.
+    fn receive_owned<T: DeserializeOwned + Send + 'static>(&mut self) -> Result<T, Error> {
+        self.receive()
+    }
+
+    /// Like [`Transport::receive_owned`], but receives a request of a
+    /// service generated by the [`service`](crate::service) macro.
+    /// Transports naming methods on the wire (e.g. JSON-RPC) override it to
+    /// resolve them against the methods of the service.
+    fn receive_request<R>(&mut self) -> Result<Envelope<R>, Error>
+    where
+        R: ServiceRequest + DeserializeOwned + Send + 'static,
+    {
+        self.receive_owned()
+    }
+
+    /// Like [`Transport::call`], but takes the request by value and passes
+    /// it as [`Transport::send_owned`] does.
+    fn call_owned<B, Out>(&mut self, request: Envelope<B>) -> Result<Out, Error>
+    where
+        B: Serialize + Send + 'static,
+        Out: DeserializeOwned + Send + 'static,
+    {
+        self.call(&request)
+    }
+
+    /// Ends the session politely: flushes what was sent and, where the
+    /// protocol allows it, tells the peer that nothing more follows, so
+    /// that it ends with [`Error::ConnectionClosed`] rather than an error.
+    /// The transport should be dropped afterwards.
+    fn close(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Total number of bytes sent and received so far. Transports which
+    /// don't track it return zeros.
+    fn byte_count(&self) -> ByteCount {
+        ByteCount::default()
+    }
+
+    /// Identity of the remote side, if it was authenticated by the
+    /// underlying stream (e.g. with a TLS client certificate).
+    fn peer_identity(&self) -> Option<&PeerIdentity> {
+        None
+    }
+
+    /// Address of the remote side, if the transport knows it.
+    fn peer_addr(&self) -> Option<&str> {
+        None
+    }
 }
 
-pub struct Bincode<S> {
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ByteCount {
+    pub sent: u64,
+    pub received: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerIdentity {
+    /// DER encoded certificate chain presented by the peer, starting with
+    /// the peer's own certificate.
+    pub certificates: Vec<Vec<u8>>,
+}
+
+/// Stream knowing the address of its remote side, which transports built
+/// with e.g. [`Framed::from_stream`] report in errors of calls.
+pub trait PeerAddr {
+    fn peer_addr(&self) -> Option<String>;
+}
+
+impl PeerAddr for std::net::TcpStream {
+    fn peer_addr(&self) -> Option<String> {
+        std::net::TcpStream::peer_addr(self)
+            .ok()
+            .map(|addr| addr.to_string())
+    }
+}
+
+#[cfg(unix)]
+impl PeerAddr for std::os::unix::net::UnixStream {
+    fn peer_addr(&self) -> Option<String> {
+        let addr = std::os::unix::net::UnixStream::peer_addr(self).ok()?;
+        addr.as_pathname().map(|path| path.display().to_string())
+    }
+}
+
+/// Serialization format used by [`Framed`] transport.
+pub trait Codec: Send + 'static {
+    /// Encoded messages are UTF-8 text, which message oriented transports
+    /// can mark as such.
+    const TEXT: bool = false;
+
+    fn encode<W: Write, T: Serialize>(writer: &mut W, data: &T) -> Result<(), Error>;
+
+    fn decode<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<T, Error>;
+}
+
+/// Transport sending messages encoded with codec `C` over stream `S`.
+pub struct Framed<C, S> {
     stream: S,
+    byte_count: ByteCount,
+    peer_identity: Option<PeerIdentity>,
+    peer_addr: Option<String>,
+    _codec: PhantomData<C>,
 }
 
-impl<S> Bincode<S> {
-    pub fn new(stream: S) -> Bincode<S> {
-        Bincode { stream }
+impl<C, S> Framed<C, S> {
+    pub fn new(stream: S) -> Framed<C, S> {
+        Framed {
+            stream,
+            byte_count: ByteCount::default(),
+            peer_identity: None,
+            peer_addr: None,
+            _codec: PhantomData,
+        }
+    }
+
+    pub fn with_peer_identity(mut self, identity: Option<PeerIdentity>) -> Framed<C, S> {
+        self.peer_identity = identity;
+        self
+    }
+
+    /// Sets address of the remote side, reported in errors of calls.
+    pub fn with_peer_addr<A: ToString>(mut self, addr: A) -> Framed<C, S> {
+        self.peer_addr = Some(addr.to_string());
+        self
     }
 }
 
-impl<S: Read + Write + Send + 'static> Transport for Bincode<S> {
+impl<C, S: PeerAddr> Framed<C, S> {
+    /// Like [`Framed::new`], but takes address of the remote side from the
+    /// stream.
+    pub fn from_stream(stream: S) -> Framed<C, S> {
+        let peer_addr = stream.peer_addr();
+        Framed {
+            peer_addr,
+            ..Framed::new(stream)
+        }
+    }
+}
+
+impl<C: Codec, S: Read + Write + Send + 'static> Transport for Framed<C, S> {
     fn receive<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
-        bincode::deserialize_from(&mut self.stream)
-            .map_err(|e| Error::MsgDeserFailed(e.to_string()))
+        let mut reader = MessageStart::new(
+            Counting::new(&mut self.stream, &mut self.byte_count.received),
+            C::TEXT,
+        );
+
+        match C::decode(&mut reader) {
+            Err(_) if reader.closed => Err(Error::ConnectionClosed),
+            // Streams with their own framing, e.g. compressed ones, report
+            // malformed frames as invalid data
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::InvalidData => {
+                Err(Error::Decode(e.into()))
+            }
+            result => result,
+        }
     }
 
     fn send<T: Serialize>(&mut self, data: &T) -> Result<(), Error> {
-        bincode::serialize_into(&mut self.stream, data)
-            .map_err(|e| Error::MsgSerFailed(e.to_string()))
+        let mut writer = Counting::new(&mut self.stream, &mut self.byte_count.sent);
+        C::encode(&mut writer, data)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Only flushes the stream, as streams have no common way of shutting
+    /// down writing. The peer sees [`Error::ConnectionClosed`] once the
+    /// stream is dropped.
+    fn close(&mut self) -> Result<(), Error> {
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    fn byte_count(&self) -> ByteCount {
+        self.byte_count
+    }
+
+    fn peer_identity(&self) -> Option<&PeerIdentity> {
+        self.peer_identity.as_ref()
+    }
+
+    fn peer_addr(&self) -> Option<&str> {
+        self.peer_addr.as_deref()
     }
 }
 
-pub struct Json<S> {
-    stream: S,
+pub type Bincode<S> = Framed<BincodeCodec, S>;
+
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    fn encode<W: Write, T: Serialize>(writer: &mut W, data: &T) -> Result<(), Error> {
+        bincode::serialize_into(writer, data).map_err(|e| Error::from_bincode(e, Error::Encode))
+    }
+
+    fn decode<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<T, Error> {
+        bincode::deserialize_from(reader).map_err(|e| Error::from_bincode(e, Error::Decode))
+    }
 }
 
-impl<S> Json<S> {
-    pub fn new(stream: S) -> Json<S> {
-        Json { stream }
+/// Newline delimited JSON.
+pub type Json<S> = Framed<JsonCodec, S>;
+
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    const TEXT: bool = true;
+
+    fn encode<W: Write, T: Serialize>(writer: &mut W, data: &T) -> Result<(), Error> {
+        serde_json::to_writer(&mut *writer, data)
+            .map_err(|e| Error::from_json(e, Error::Encode))?;
+        // Delimiter lets the reader find the end of top-level numbers
+        // without waiting for the next message
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn decode<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<T, Error> {
+        // Unlike `serde_json::from_reader` this does not wait for the end of
+        // the stream
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        T::deserialize(&mut deserializer).map_err(|e| Error::from_json(e, Error::Decode))
     }
 }
 
-impl<S: Read + Write + Send + 'static> Transport for Json<S> {
-    fn receive<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
-        serde_json::from_reader(&mut self.stream).map_err(|e| Error::MsgDeserFailed(e.to_string()))
+#[cfg(feature = "msgpack")]
+pub type MsgPack<S> = Framed<MsgPackCodec, S>;
+
+/// MessagePack with structs encoded as maps, so that field names are
+/// visible to clients in other languages.
+#[cfg(feature = "msgpack")]
+pub struct MsgPackCodec;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MsgPackCodec {
+    fn encode<W: Write, T: Serialize>(writer: &mut W, data: &T) -> Result<(), Error> {
+        rmp_serde::encode::write_named(writer, data).map_err(Error::from_msgpack_encode)
     }
 
-    fn send<T: Serialize>(&mut self, data: &T) -> Result<(), Error> {
-        serde_json::to_writer(&mut self.stream, data)
-            .map_err(|e| Error::MsgSerFailed(e.to_string()))
+    fn decode<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<T, Error> {
+        rmp_serde::decode::from_read(reader).map_err(Error::from_msgpack_decode)
+    }
+}
+
+#[cfg(feature = "cbor")]
+pub type Cbor<S> = Framed<CborCodec, S>;
+
+#[cfg(feature = "cbor")]
+pub struct CborCodec;
+
+#[cfg(feature = "cbor")]
+impl Codec for CborCodec {
+    fn encode<W: Write, T: Serialize>(writer: &mut W, data: &T) -> Result<(), Error> {
+        ciborium::into_writer(data, writer).map_err(Error::from_cbor_encode)
+    }
+
+    fn decode<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<T, Error> {
+        ciborium::from_reader(reader).map_err(Error::from_cbor_decode)
+    }
+}
+
+/// Postcard messages longer than this are rejected, rather than allocating
+/// whatever length the peer claims.
+#[cfg(feature = "postcard")]
+pub const MAX_POSTCARD_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+#[cfg(feature = "postcard")]
+pub type Postcard<S> = Framed<PostcardCodec, S>;
+
+/// Postcard can't decode directly from a stream, so every message is
+/// prefixed with its length as a little endian `u32`.
+#[cfg(feature = "postcard")]
+pub struct PostcardCodec;
+
+#[cfg(feature = "postcard")]
+impl Codec for PostcardCodec {
+    fn encode<W: Write, T: Serialize>(writer: &mut W, data: &T) -> Result<(), Error> {
+        let message = postcard::to_stdvec(data).map_err(Error::encode)?;
+        let len = u32::try_from(message.len()).map_err(Error::encode)?;
+
+        writer.write_all(&len.to_le_bytes())?;
+        writer.write_all(&message)?;
+        Ok(())
+    }
+
+    fn decode<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<T, Error> {
+        let mut len = [0; 4];
+        reader.read_exact(&mut len)?;
+
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_POSTCARD_MESSAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "message of {} bytes exceeds maximum of {} bytes",
+                    len, MAX_POSTCARD_MESSAGE_SIZE
+                ),
+            )
+            .into());
+        }
+
+        let mut message = vec![0; len];
+        reader.read_exact(&mut message)?;
+
+        postcard::from_bytes(&message).map_err(Error::decode)
+    }
+}
+
+/// Reader noting whether the stream ended before the first byte of a
+/// message, i.e. the peer closed the connection between messages.
+struct MessageStart<R> {
+    reader: R,
+    /// Whitespace between messages of text codecs, e.g. the delimiter after
+    /// the previous message, doesn't start the next one.
+    text: bool,
+    started: bool,
+    closed: bool,
+}
+
+impl<R> MessageStart<R> {
+    fn new(reader: R, text: bool) -> MessageStart<R> {
+        MessageStart {
+            reader,
+            text,
+            started: false,
+            closed: false,
+        }
+    }
+}
+
+impl<R: Read> Read for MessageStart<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = match self.reader.read(buf) {
+            Ok(len) => len,
+            // Streams like TLS report the peer going away without closing
+            // the session as an unexpected end of the stream
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && !self.started => {
+                self.closed = true;
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+
+        if len == 0 {
+            if !buf.is_empty() && !self.started {
+                self.closed = true;
+            }
+        } else if !self.text || buf[..len].iter().any(|b| !b.is_ascii_whitespace()) {
+            self.started = true;
+        }
+        Ok(len)
+    }
+}
+
+/// Stream adapter counting bytes passing through it.
+pub(crate) struct Counting<'a, S> {
+    stream: &'a mut S,
+    count: &'a mut u64,
+}
+
+impl<'a, S> Counting<'a, S> {
+    pub(crate) fn new(stream: &'a mut S, count: &'a mut u64) -> Counting<'a, S> {
+        Counting { stream, count }
+    }
+}
+
+impl<S: Read> Read for Counting<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.stream.read(buf)?;
+        *self.count += len as u64;
+        Ok(len)
+    }
+}
+
+impl<S: Write> Write for Counting<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.stream.write(buf)?;
+        *self.count += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
     }
 }
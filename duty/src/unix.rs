@@ -0,0 +1,204 @@
+use crate::listener::{Listener, Waker};
+use std::ffi::{CString, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// Unix domain socket listener which removes its socket file when dropped.
+pub struct UnixSocketListener {
+    listener: UnixListener,
+    path: Option<PathBuf>,
+}
+
+impl UnixSocketListener {
+    /// Binds socket at `path`. Socket file left behind by a server which is
+    /// no longer running is removed first.
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<UnixSocketListener> {
+        let path = path.as_ref();
+
+        if is_stale_socket(path)? {
+            fs::remove_file(path)?;
+        }
+
+        Ok(UnixSocketListener {
+            listener: UnixListener::bind(path)?,
+            path: Some(path.to_owned()),
+        })
+    }
+
+    /// Binds socket in the abstract namespace. Such socket has no file in the
+    /// file system and disappears together with the listener.
+    #[cfg(target_os = "linux")]
+    pub fn bind_abstract(name: impl AsRef<[u8]>) -> io::Result<UnixSocketListener> {
+        use std::os::linux::net::SocketAddrExt;
+
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+
+        Ok(UnixSocketListener {
+            listener: UnixListener::bind_addr(&addr)?,
+            path: None,
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+}
+
+impl Listener for UnixSocketListener {
+    type Stream = UnixStream;
+
+    fn accept(&self) -> io::Result<UnixStream> {
+        self.listener.accept().map(|(stream, _)| stream)
+    }
+
+    /// Connects to the socket, which works for abstract sockets too.
+    fn waker(&self) -> io::Result<Waker> {
+        let addr = self.listener.local_addr()?;
+
+        Ok(Box::new(move || {
+            let _ = UnixStream::connect_addr(&addr);
+        }))
+    }
+}
+
+impl Drop for UnixSocketListener {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            if let Err(e) = fs::remove_file(path) {
+                tracing::warn!("cannot remove socket file {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+pub fn connect(path: impl AsRef<Path>) -> io::Result<UnixStream> {
+    UnixStream::connect(path)
+}
+
+#[cfg(target_os = "linux")]
+pub fn connect_abstract(name: impl AsRef<[u8]>) -> io::Result<UnixStream> {
+    use std::os::linux::net::SocketAddrExt;
+
+    let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+    UnixStream::connect_addr(&addr)
+}
+
+fn is_stale_socket(path: &Path) -> io::Result<bool> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => match UnixStream::connect(path) {
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("socket {} is in use", path.display()),
+            )),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => Ok(true),
+            Err(e) => Err(e),
+        },
+        Ok(_) => Ok(false),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Duplex stream over two named pipes (FIFOs), one for each direction.
+///
+/// Unlike sockets, the pipes connect a single pair of peers: the side which
+/// created them with [`NamedPipe::create`] and the side which opened them
+/// with [`NamedPipe::connect`]. Both calls block until the other side comes.
+pub struct NamedPipe {
+    reader: File,
+    writer: File,
+    _files: Option<PipeFiles>,
+}
+
+impl NamedPipe {
+    /// Creates pipes `<path>.up` and `<path>.down` and waits for the peer to
+    /// open them. The pipes are removed when the stream is dropped.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<NamedPipe> {
+        let (up, down) = pipe_paths(path.as_ref());
+
+        mkfifo(&up)?;
+        let mut files = PipeFiles(vec![up.clone()]);
+        mkfifo(&down)?;
+        files.0.push(down.clone());
+
+        // Pipes are opened in the same order on both sides, as opening one
+        // end blocks until the other end is opened too
+        let reader = File::open(&up)?;
+        let writer = OpenOptions::new().write(true).open(&down)?;
+
+        Ok(NamedPipe {
+            reader,
+            writer,
+            _files: Some(files),
+        })
+    }
+
+    /// Opens pipes created by [`NamedPipe::create`] at `path`.
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<NamedPipe> {
+        let (up, down) = pipe_paths(path.as_ref());
+
+        let writer = OpenOptions::new().write(true).open(&up)?;
+        let reader = File::open(&down)?;
+
+        Ok(NamedPipe {
+            reader,
+            writer,
+            _files: None,
+        })
+    }
+}
+
+impl Read for NamedPipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl Write for NamedPipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Pipe files removed when dropped.
+struct PipeFiles(Vec<PathBuf>);
+
+impl Drop for PipeFiles {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            if let Err(e) = fs::remove_file(path) {
+                tracing::warn!("cannot remove pipe {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Paths of the pipe from the connecting side to the creating one and back.
+fn pipe_paths(path: &Path) -> (PathBuf, PathBuf) {
+    let with_suffix = |suffix: &str| {
+        let mut path = OsString::from(path);
+        path.push(suffix);
+        PathBuf::from(path)
+    };
+
+    (with_suffix(".up"), with_suffix(".down"))
+}
+
+fn mkfifo(path: &Path) -> io::Result<()> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+
+    // SAFETY: `path` is a valid NUL terminated string
+    if unsafe { libc::mkfifo(path.as_ptr(), 0o600) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
@@ -0,0 +1,154 @@
+use crate::error::Error;
+use crate::listener::{self, Listener};
+use crate::transport::{ByteCount, Codec, PeerAddr, Transport};
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use tungstenite::handshake::{HandshakeError, HandshakeRole};
+use tungstenite::{Message, WebSocket};
+
+/// Transport sending every message as a single WebSocket message encoded
+/// with codec `C`.
+///
+/// Text codecs (JSON) use text messages, others use binary ones. Pings are
+/// answered automatically, closing the socket ends the connection with
+/// [`Error::ConnectionClosed`]. Byte count covers message payloads
+/// only, not WebSocket framing.
+pub struct WebSocketTransport<C, S> {
+    socket: WebSocket<S>,
+    byte_count: ByteCount,
+    peer_addr: Option<String>,
+    _codec: PhantomData<C>,
+}
+
+impl<C, S: Read + Write> WebSocketTransport<C, S> {
+    /// Wraps socket which already completed the opening handshake.
+    pub fn new(socket: WebSocket<S>) -> WebSocketTransport<C, S> {
+        WebSocketTransport {
+            socket,
+            byte_count: ByteCount::default(),
+            peer_addr: None,
+            _codec: PhantomData,
+        }
+    }
+
+    /// Performs server side of the opening handshake on `stream`.
+    pub fn accept(stream: S) -> Result<WebSocketTransport<C, S>, Error> {
+        let socket = tungstenite::accept(stream).map_err(map_handshake_error)?;
+        Ok(WebSocketTransport::new(socket))
+    }
+
+    /// Performs client side of the opening handshake on `stream`, `url` is
+    /// e.g. `ws://localhost:8080/stats`.
+    pub fn connect(url: &str, stream: S) -> Result<WebSocketTransport<C, S>, Error> {
+        let (socket, _) = tungstenite::client(url, stream).map_err(map_handshake_error)?;
+        Ok(WebSocketTransport::new(socket))
+    }
+
+    pub fn into_inner(self) -> WebSocket<S> {
+        self.socket
+    }
+}
+
+impl<C, S: Read + Write + PeerAddr> WebSocketTransport<C, S> {
+    /// Like [`WebSocketTransport::connect`], but takes address of the
+    /// remote side from the stream.
+    pub fn connect_stream(url: &str, stream: S) -> Result<WebSocketTransport<C, S>, Error> {
+        let peer_addr = stream.peer_addr();
+        let mut transport = WebSocketTransport::connect(url, stream)?;
+        transport.peer_addr = peer_addr;
+        Ok(transport)
+    }
+}
+
+impl<C, S> Transport for WebSocketTransport<C, S>
+where
+    C: Codec,
+    S: Read + Write + Send + 'static,
+{
+    fn receive<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        loop {
+            let payload = match self.socket.read().map_err(map_error)? {
+                Message::Binary(payload) => payload,
+                Message::Text(payload) => payload.into(),
+                Message::Close(_) => {
+                    // Sends the reply completing the closing handshake
+                    let _ = self.socket.flush();
+                    return Err(Error::ConnectionClosed);
+                }
+                Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => continue,
+            };
+
+            self.byte_count.received += payload.len() as u64;
+            return C::decode(&mut payload.as_ref());
+        }
+    }
+
+    fn send<T: Serialize>(&mut self, data: &T) -> Result<(), Error> {
+        let mut payload = Vec::new();
+        C::encode(&mut payload, data)?;
+        self.byte_count.sent += payload.len() as u64;
+
+        let message = if C::TEXT {
+            let text = String::from_utf8(payload).map_err(Error::encode)?;
+            Message::text(text)
+        } else {
+            Message::binary(payload)
+        };
+
+        self.socket.send(message).map_err(map_error)
+    }
+
+    /// Performs the closing handshake, waiting for the peer to confirm it.
+    fn close(&mut self) -> Result<(), Error> {
+        let mut result = self.socket.close(None);
+
+        while result.is_ok() {
+            result = self.socket.read().map(drop);
+        }
+
+        match result.map_err(map_error) {
+            Err(Error::ConnectionClosed) => Ok(()),
+            result => result,
+        }
+    }
+
+    fn byte_count(&self) -> ByteCount {
+        self.byte_count
+    }
+
+    fn peer_addr(&self) -> Option<&str> {
+        self.peer_addr.as_deref()
+    }
+}
+
+/// Accepts connections, performs WebSocket handshake on each of them and
+/// serves them in separate threads, see [`listener::serve`].
+pub fn serve<L, C, F>(listener: &L, serve_connection: F) -> io::Result<()>
+where
+    L: Listener,
+    C: Codec,
+    F: Fn(WebSocketTransport<C, L::Stream>) -> Result<(), Error> + Send + Sync + 'static,
+{
+    listener::serve_streams(listener, move |stream| {
+        serve_connection(WebSocketTransport::accept(stream)?)
+    })
+}
+
+fn map_error(e: tungstenite::Error) -> Error {
+    match e {
+        tungstenite::Error::Io(e) => Error::Io(e),
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            Error::ConnectionClosed
+        }
+        e => Error::Io(io::Error::new(io::ErrorKind::InvalidData, e)),
+    }
+}
+
+fn map_handshake_error<R: HandshakeRole>(e: HandshakeError<R>) -> Error {
+    match e {
+        HandshakeError::Failure(e) => map_error(e),
+        // Only non-blocking streams get interrupted
+        HandshakeError::Interrupted(_) => Error::Io(io::ErrorKind::WouldBlock.into()),
+    }
+}
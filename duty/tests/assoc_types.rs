@@ -0,0 +1,78 @@
+use duty::error::Error;
+use duty::stream::MpscStream;
+use duty::{service, transport};
+use serde::{de::DeserializeOwned, Serialize};
+
+#[service]
+trait Store {
+    type Key: Serialize + DeserializeOwned;
+    type Value: Serialize + DeserializeOwned + Clone;
+
+    const CAPACITY: usize = 16;
+
+    fn put(&mut self, key: Self::Key, value: Self::Value) -> Option<Self::Value>;
+    fn get(&self, key: Self::Key) -> Option<Self::Value>;
+    fn len(&self) -> usize;
+
+    // Local helper, not part of the remote interface
+    fn is_full(&self) -> bool {
+        self.len() >= Self::CAPACITY
+    }
+}
+
+#[derive(Default)]
+struct MapStore(std::collections::HashMap<String, i32>);
+
+impl Store for MapStore {
+    type Key = String;
+    type Value = i32;
+
+    fn put(&mut self, key: String, value: i32) -> Option<i32> {
+        self.0.insert(key, value)
+    }
+
+    fn get(&self, key: String) -> Option<i32> {
+        self.0.get(&key).cloned()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+#[test]
+fn assoc_types() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+
+        s.spawn(|| -> Result<(), Error> {
+            let mut transport = transport::Bincode::new(server_stream);
+            let mut store = MapStore::default();
+            for _ in 0..4 {
+                store.handle_next_request(&mut transport)?;
+            }
+            assert!(!store.is_full());
+            Ok(())
+        });
+
+        let client = StoreClient::<_, String, i32>::new(transport::Bincode::new(client_stream))?;
+
+        assert_eq!(client.put("a".to_owned(), 1)?, None);
+        assert_eq!(client.put("a".to_owned(), 2)?, Some(1));
+        assert_eq!(client.get("a".to_owned())?, Some(2));
+        assert_eq!(client.len()?, 1);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn assoc_types_request() {
+    // Only the methods without default implementation are dispatched
+    let request = StoreRequest::<String, i32>::Get {
+        key: "a".to_owned(),
+    };
+    let json = serde_json::to_string(&request).unwrap();
+    assert_eq!(json, r#"{"get":{"key":"a"}}"#);
+    assert!(serde_json::from_str::<StoreRequest<String, i32>>(r#"{"is_full":{}}"#).is_err());
+}
@@ -0,0 +1,104 @@
+use duty::auth::{Authenticator, HmacAuth, TokenAuth};
+use duty::error::Error;
+use duty::listener::{ServerBuilder, Shutdown};
+use duty::stream::MpscStream;
+use duty::{service, transport};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+
+#[service]
+trait SecretService {
+    fn secret(&self) -> u32;
+}
+
+#[derive(Clone)]
+struct SecretServiceServer;
+
+impl SecretService for SecretServiceServer {
+    fn secret(&self) -> u32 {
+        42
+    }
+}
+
+fn call_secret<C, S>(client_auth: C, server_auth: S) -> (Result<u32, Error>, Result<(), Error>)
+where
+    C: Authenticator,
+    S: Authenticator + Sync,
+{
+    std::thread::scope(|s| {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+
+        let server = s.spawn(|| -> Result<(), Error> {
+            let mut transport = transport::Bincode::new(server_stream);
+            server_auth.verify(&mut transport)?;
+            SecretServiceServer.handle_next_request(&mut transport)
+        });
+
+        let transport = transport::Bincode::new(client_stream);
+        let result = SecretServiceClient::with_auth(transport, &client_auth)
+            .and_then(|client| client.secret());
+
+        (result, server.join().expect("Thread panicked"))
+    })
+}
+
+#[test]
+fn token_auth() {
+    let (client, server) =
+        call_secret(TokenAuth::new("open sesame"), TokenAuth::new("open sesame"));
+    assert_eq!(client.unwrap(), 42);
+    assert!(server.is_ok());
+
+    let (client, server) = call_secret(TokenAuth::new("guess"), TokenAuth::new("open sesame"));
+    assert!(matches!(client, Err(Error::Unauthenticated)));
+    assert!(matches!(server, Err(Error::Unauthenticated)));
+}
+
+#[test]
+fn hmac_auth() {
+    let (client, server) = call_secret(HmacAuth::new("secret"), HmacAuth::new("secret"));
+    assert_eq!(client.unwrap(), 42);
+    assert!(server.is_ok());
+
+    let (client, server) = call_secret(HmacAuth::new("guess"), HmacAuth::new("secret"));
+    assert!(matches!(client, Err(Error::Unauthenticated)));
+    assert!(matches!(server, Err(Error::Unauthenticated)));
+}
+
+#[test]
+fn serve_with_auth() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let connect = || -> Result<_, Error> { Ok(transport::Bincode::new(TcpStream::connect(addr)?)) };
+
+    let shutdown = Shutdown::new();
+    let (disconnected_sender, disconnected) = mpsc::channel();
+    let builder = ServerBuilder::new()
+        .shutdown(&shutdown)
+        .auth(TokenAuth::new("open sesame"))
+        .on_disconnect(move |_, result| {
+            let unauthenticated = matches!(result, Err(Error::Unauthenticated));
+            disconnected_sender
+                .send(unauthenticated)
+                .expect("test is waiting");
+        });
+
+    std::thread::scope(|s| {
+        let server = s
+            .spawn(|| SecretServiceServer.serve_with(&builder, &listener, transport::Bincode::new));
+
+        let client = SecretServiceClient::with_auth(connect()?, &TokenAuth::new("open sesame"))?;
+        assert_eq!(client.secret()?, 42);
+        drop(client);
+        assert!(!disconnected.recv().unwrap());
+
+        let result = SecretServiceClient::with_auth(connect()?, &TokenAuth::new("guess"));
+        assert!(matches!(result, Err(Error::Unauthenticated)));
+        assert!(disconnected.recv().unwrap());
+
+        shutdown.trigger();
+        server.join().expect("Thread panicked")?;
+
+        Ok(())
+    })
+}
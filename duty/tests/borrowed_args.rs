@@ -0,0 +1,95 @@
+use duty::error::Error;
+use duty::inprocess::InProcess;
+use duty::stream::MpscStream;
+use duty::{service, transport};
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+#[service]
+trait TextService {
+    fn mean(&self, data: &[f64]) -> f64;
+    fn shout(&self, text: &str, times: usize) -> String;
+    fn norm(&self, point: &Point) -> f64;
+}
+
+struct TextServiceServer;
+
+impl TextService for TextServiceServer {
+    fn mean(&self, data: &[f64]) -> f64 {
+        data.iter().sum::<f64>() / data.len() as f64
+    }
+
+    fn shout(&self, text: &str, times: usize) -> String {
+        text.to_uppercase().repeat(times)
+    }
+
+    fn norm(&self, point: &Point) -> f64 {
+        point.x.hypot(point.y)
+    }
+}
+
+fn check_calls<T: duty::Transport>(client: &TextServiceClient<T>) -> Result<(), Error> {
+    let data = vec![1.0, 2.0, 6.0];
+    assert_eq!(client.mean(&data)?, 3.0);
+    assert_eq!(client.shout("hey", 2)?, "HEYHEY");
+    assert_eq!(client.norm(&Point { x: 3.0, y: 4.0 })?, 5.0);
+    Ok(())
+}
+
+#[test]
+fn borrowed_args_bincode() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+
+        s.spawn(|| -> Result<(), Error> {
+            let mut transport = transport::Bincode::new(server_stream);
+            for _ in 0..3 {
+                TextServiceServer.handle_next_request(&mut transport)?;
+            }
+            Ok(())
+        });
+
+        check_calls(&TextServiceClient::new(transport::Bincode::new(
+            client_stream,
+        ))?)
+    })
+}
+
+#[test]
+fn borrowed_args_json() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+
+        s.spawn(|| -> Result<(), Error> {
+            let mut transport = transport::Json::new(server_stream);
+            for _ in 0..3 {
+                TextServiceServer.handle_next_request(&mut transport)?;
+            }
+            Ok(())
+        });
+
+        check_calls(&TextServiceClient::new(transport::Json::new(
+            client_stream,
+        ))?)
+    })
+}
+
+#[test]
+fn borrowed_args_inprocess() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_transport, mut server_transport) = InProcess::new_pair();
+
+        s.spawn(move || -> Result<(), Error> {
+            for _ in 0..3 {
+                TextServiceServer.handle_next_request(&mut server_transport)?;
+            }
+            Ok(())
+        });
+
+        check_calls(&TextServiceClient::new(client_transport)?)
+    })
+}
@@ -0,0 +1,133 @@
+use duty::error::Error;
+use duty::service;
+use duty::stream::MpscStream;
+use duty::transport::{Codec, Framed};
+
+#[service]
+trait StatsService {
+    fn count(&self, values: Vec<f64>) -> u64;
+    fn mean(&self, values: Vec<f64>) -> Option<f64>;
+    fn describe(&self, name: String, values: Vec<f64>) -> (String, usize);
+}
+
+struct StatsServiceServer;
+
+impl StatsService for StatsServiceServer {
+    fn count(&self, values: Vec<f64>) -> u64 {
+        values.len() as u64
+    }
+
+    fn mean(&self, values: Vec<f64>) -> Option<f64> {
+        match values.len() {
+            0 => None,
+            len => Some(values.iter().sum::<f64>() / len as f64),
+        }
+    }
+
+    fn describe(&self, name: String, values: Vec<f64>) -> (String, usize) {
+        (name, values.len())
+    }
+}
+
+fn roundtrip<C: Codec>() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+
+        s.spawn(|| -> Result<(), Error> {
+            let mut transport = Framed::<C, _>::new(server_stream);
+
+            let server = StatsServiceServer;
+            for _ in 0..4 {
+                server.handle_next_request(&mut transport)?;
+            }
+            Ok(())
+        });
+
+        let client = StatsServiceClient::new(Framed::<C, _>::new(client_stream))?;
+
+        assert_eq!(client.count(vec![1.0, 2.0, 3.0])?, 3);
+        assert_eq!(client.mean(vec![1.0, 2.0, 3.0])?, Some(2.0));
+        assert_eq!(client.mean(Vec::new())?, None);
+        assert_eq!(
+            client.describe("empty".to_owned(), Vec::new())?,
+            ("empty".to_owned(), 0)
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn bincode_roundtrip() -> Result<(), Error> {
+    roundtrip::<duty::transport::BincodeCodec>()
+}
+
+#[test]
+fn json_roundtrip() -> Result<(), Error> {
+    roundtrip::<duty::transport::JsonCodec>()
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_roundtrip() -> Result<(), Error> {
+    roundtrip::<duty::transport::MsgPackCodec>()
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_roundtrip() -> Result<(), Error> {
+    roundtrip::<duty::transport::CborCodec>()
+}
+
+/// Peer goes away in the middle of a message, which is an I/O error rather
+/// than a malformed message.
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+fn closed_within_message<C: Codec>(partial: &[u8]) {
+    use duty::Transport;
+    use std::io::{ErrorKind, Write};
+
+    let (mut client_stream, server_stream) = MpscStream::new_pair();
+    client_stream.write_all(partial).unwrap();
+    drop(client_stream);
+
+    match Framed::<C, _>::new(server_stream).receive::<(String, u32)>() {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), ErrorKind::UnexpectedEof),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_keeps_io_errors() {
+    // Array of two, string of 5 bytes, only two of them sent
+    closed_within_message::<duty::transport::MsgPackCodec>(&[0x92, 0xa5, b'a', b'b']);
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_keeps_io_errors() {
+    // Array of two, text of 5 bytes, only two of them sent
+    closed_within_message::<duty::transport::CborCodec>(&[0x82, 0x65, b'a', b'b']);
+}
+
+#[cfg(feature = "postcard")]
+#[test]
+fn postcard_roundtrip() -> Result<(), Error> {
+    roundtrip::<duty::transport::PostcardCodec>()
+}
+
+#[cfg(feature = "postcard")]
+#[test]
+fn postcard_rejects_oversized_length() -> Result<(), Error> {
+    use duty::Transport;
+    use std::io::Write;
+
+    let (mut client_stream, server_stream) = MpscStream::new_pair();
+    client_stream.write_all(&u32::MAX.to_le_bytes())?;
+    client_stream.flush()?;
+
+    let result = duty::transport::Postcard::new(server_stream).receive::<Vec<u8>>();
+    assert!(matches!(result, Err(Error::Decode(_))));
+
+    Ok(())
+}
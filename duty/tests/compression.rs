@@ -0,0 +1,129 @@
+#![cfg(any(feature = "lz4", feature = "zstd"))]
+
+use duty::compression::{Algorithm, Compressed};
+use duty::error::Error;
+use duty::stream::MpscStream;
+use duty::transport::{Bincode, Json};
+use duty::Transport;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Stream counting bytes written to it.
+struct Counted {
+    stream: MpscStream,
+    written: Arc<AtomicU64>,
+}
+
+impl Read for Counted {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for Counted {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.stream.write(buf)?;
+        self.written.fetch_add(len as u64, Ordering::SeqCst);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Sends a short and a long series through compressed transports. Returns
+/// algorithm negotiated by the sender and number of bytes it sent.
+fn send_series(sender_algorithms: &[Algorithm]) -> Result<(Option<Algorithm>, u64), Error> {
+    std::thread::scope(|s| {
+        let (sender_stream, receiver_stream) = MpscStream::new_pair();
+
+        let receiver = s.spawn(|| -> Result<(), Error> {
+            let mut transport = Bincode::new(Compressed::negotiate(receiver_stream)?);
+            assert_eq!(transport.receive::<Vec<f64>>()?, vec![1.5; 2]);
+            assert_eq!(transport.receive::<Vec<f64>>()?, vec![1.5; 10_000]);
+            Ok(())
+        });
+
+        let written = Arc::new(AtomicU64::new(0));
+        let stream = Counted {
+            stream: sender_stream,
+            written: written.clone(),
+        };
+        let compressed = Compressed::negotiate_with(stream, sender_algorithms, 64)?;
+        let algorithm = compressed.algorithm();
+        let start = written.load(Ordering::SeqCst);
+
+        let mut transport = Bincode::new(compressed);
+        transport.send(&vec![1.5; 2])?;
+        transport.send(&vec![1.5; 10_000])?;
+
+        receiver.join().expect("Thread panicked")?;
+
+        Ok((algorithm, written.load(Ordering::SeqCst) - start))
+    })
+}
+
+#[test]
+fn compression() -> Result<(), Error> {
+    let (algorithm, sent) = send_series(Algorithm::ALL)?;
+    assert_eq!(algorithm, Some(Algorithm::ALL[0]));
+    assert!(sent < 10_000);
+
+    for algorithm in Algorithm::ALL {
+        let (negotiated, sent) = send_series(&[*algorithm])?;
+        assert_eq!(negotiated, Some(*algorithm));
+        assert!(sent < 10_000);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn compression_not_supported_by_peer() -> Result<(), Error> {
+    let (algorithm, sent) = send_series(&[])?;
+    assert_eq!(algorithm, None);
+    assert!(sent > 80_000);
+
+    Ok(())
+}
+
+#[test]
+fn compression_under_json() -> Result<(), Error> {
+    let (client_stream, server_stream) = MpscStream::new_pair();
+    let server = std::thread::spawn(move || -> Result<Vec<String>, Error> {
+        Json::new(Compressed::negotiate(server_stream)?).receive()
+    });
+
+    let mut transport = Json::new(Compressed::negotiate(client_stream)?);
+    let message = vec!["duty".to_owned(); 1000];
+    transport.send(&message)?;
+
+    assert_eq!(server.join().expect("Thread panicked")?, message);
+    assert!(transport.byte_count().sent > 7000);
+
+    Ok(())
+}
+
+#[test]
+fn oversized_frame_rejected() -> Result<(), Error> {
+    for algorithm in Algorithm::ALL {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+        let algorithms = [*algorithm];
+        let server = std::thread::spawn(move || -> Result<Vec<u8>, Error> {
+            let stream = Compressed::negotiate_with(server_stream, &algorithms, 64)?;
+            Bincode::new(stream.max_frame_size(1000)).receive()
+        });
+
+        // Compresses to far less than the limit of the receiver
+        let mut transport =
+            Bincode::new(Compressed::negotiate_with(client_stream, &algorithms, 64)?);
+        transport.send(&vec![0u8; 100_000])?;
+
+        let result = server.join().expect("Thread panicked");
+        assert!(matches!(result, Err(Error::Decode(_))), "{:?}", result);
+    }
+
+    Ok(())
+}
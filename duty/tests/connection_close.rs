@@ -0,0 +1,145 @@
+use duty::client::Close;
+use duty::error::Error;
+use duty::inprocess::InProcess;
+use duty::stream::MpscStream;
+use duty::{service, transport, Transport};
+use std::error::Error as _;
+use std::io::{ErrorKind, Write};
+
+#[service]
+trait Ping {
+    fn ping(&self) -> u32;
+}
+
+struct PingServer;
+
+#[service]
+trait Door {
+    fn close(&self) -> bool;
+}
+
+struct DoorServer;
+
+impl Door for DoorServer {
+    fn close(&self) -> bool {
+        true
+    }
+}
+
+impl Ping for PingServer {
+    fn ping(&self) -> u32 {
+        7
+    }
+}
+
+#[test]
+fn closed_between_messages() -> Result<(), Error> {
+    let (client_stream, server_stream) = MpscStream::new_pair();
+    let mut transport = transport::Bincode::new(server_stream);
+
+    let mut client_transport = transport::Bincode::new(client_stream);
+    client_transport.send(&42u32)?;
+    client_transport.close()?;
+    drop(client_transport);
+
+    assert_eq!(transport.receive::<u32>()?, 42);
+    assert!(matches!(
+        transport.receive::<u32>(),
+        Err(Error::ConnectionClosed)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn json_closed_between_messages() -> Result<(), Error> {
+    let (client_stream, server_stream) = MpscStream::new_pair();
+    let mut transport = transport::Json::new(server_stream);
+
+    // Delimiter after the message doesn't start the next one
+    let mut client_transport = transport::Json::new(client_stream);
+    client_transport.send(&42u32)?;
+    client_transport.close()?;
+    drop(client_transport);
+
+    assert_eq!(transport.receive::<u32>()?, 42);
+    assert!(matches!(
+        transport.receive::<u32>(),
+        Err(Error::ConnectionClosed)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn json_serve_ends_on_close() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+
+        let server = s.spawn(|| PingServer.serve(transport::Json::new(server_stream)));
+
+        let client = PingClient::new(transport::Json::new(client_stream))?;
+        assert_eq!(client.ping()?, 7);
+        Close::close(client)?;
+
+        server.join().expect("Thread panicked")
+    })
+}
+
+#[test]
+fn closed_within_message() {
+    let (mut client_stream, server_stream) = MpscStream::new_pair();
+    let mut transport = transport::Bincode::new(server_stream);
+
+    client_stream.write_all(&[1, 2]).unwrap();
+    drop(client_stream);
+
+    match transport.receive::<u32>() {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), ErrorKind::UnexpectedEof),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn decode_error_source() {
+    let (mut client_stream, server_stream) = MpscStream::new_pair();
+    let mut transport = transport::Json::new(server_stream);
+
+    client_stream.write_all(b"\"text\"\n").unwrap();
+
+    let error = transport.receive::<u32>().unwrap_err();
+    assert!(matches!(error, Error::Decode(_)));
+    let source = error.source().expect("decode error has a source");
+    assert!(source.downcast_ref::<serde_json::Error>().is_some());
+}
+
+#[test]
+fn client_close() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_transport, server_transport) = InProcess::new_pair();
+
+        let server = s.spawn(|| PingServer.serve(server_transport));
+
+        let client = PingClient::new(client_transport)?;
+        assert_eq!(client.ping()?, 7);
+        Close::close(client)?;
+
+        server.join().expect("Thread panicked")
+    })
+}
+
+#[test]
+fn client_close_with_close_method() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_transport, server_transport) = InProcess::new_pair();
+
+        let server = s.spawn(|| DoorServer.serve(server_transport));
+
+        // Service method doesn't end the session
+        let client = DoorClient::new(client_transport)?;
+        assert!(client.close()?);
+        Close::close(client)?;
+
+        server.join().expect("Thread panicked")
+    })
+}
@@ -0,0 +1,89 @@
+use duty::error::{CallContext, Error};
+use duty::inprocess::InProcess;
+use duty::stream::MpscStream;
+use duty::{service, transport};
+use std::error::Error as _;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+
+#[service]
+#[allow(dead_code)]
+trait Clock {
+    fn now(&self) -> u64;
+}
+
+#[test]
+fn call_context() -> Result<(), Error> {
+    let (client_stream, server_stream) = MpscStream::new_pair();
+    drop(server_stream);
+
+    let transport = transport::Bincode::new(client_stream).with_peer_addr("10.0.0.1:4000");
+    let client = ClockClient::new(transport)?;
+
+    let error = client.now().unwrap_err();
+    assert_eq!(
+        error.context(),
+        Some(&CallContext::new("Clock", "now", Some("10.0.0.1:4000")))
+    );
+    assert!(matches!(error.root(), Error::Io(e) if e.kind() == io::ErrorKind::BrokenPipe));
+    assert!(error.is_retryable());
+    assert!(error
+        .to_string()
+        .starts_with("call to Clock::now at 10.0.0.1:4000: "));
+
+    // Source chain leads to the original I/O error
+    let source = std::iter::successors(error.source(), |&e| e.source())
+        .find_map(|e| e.downcast_ref::<io::Error>())
+        .expect("I/O error in the source chain");
+    assert_eq!(source.kind(), io::ErrorKind::BrokenPipe);
+
+    Ok(())
+}
+
+#[test]
+fn peer_addr_from_stream() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let stream = TcpStream::connect(addr)?;
+    drop(listener.accept()?);
+
+    let client = ClockClient::new(transport::Bincode::from_stream(stream))?;
+    let error = client.now().unwrap_err();
+    let context = error.context().expect("error has call context");
+    assert_eq!(context.peer_addr, Some(addr.to_string()));
+
+    let (client_transport, _) = InProcess::new_pair();
+    let error = ClockClient::new(client_transport)?.now().unwrap_err();
+    assert_eq!(
+        error.context(),
+        Some(&CallContext::new("Clock", "now", Some("in-process")))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn retryable_errors() {
+    assert!(Error::ConnectionClosed.is_retryable());
+    assert!(Error::Io(io::ErrorKind::TimedOut.into()).is_retryable());
+    assert!(!Error::Io(io::ErrorKind::PermissionDenied.into()).is_retryable());
+    assert!(!Error::decode("bad message").is_retryable());
+    assert!(!Error::Unauthenticated.is_retryable());
+    assert!(Error::Http {
+        status: 503,
+        body: String::new()
+    }
+    .is_retryable());
+
+    // Application error codes of e.g. JSON-RPC are not HTTP statuses
+    assert!(!Error::Remote {
+        code: 503,
+        message: String::new()
+    }
+    .is_retryable());
+
+    // Context doesn't change the classification
+    let error = Error::ConnectionClosed.with_context(|| CallContext::new("S", "m", None));
+    assert!(error.is_retryable());
+    assert_eq!(error.to_string(), "call to S::m: connection closed");
+}
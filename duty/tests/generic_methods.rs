@@ -0,0 +1,64 @@
+use duty::error::Error;
+use duty::stream::MpscStream;
+use duty::{service, transport};
+use serde::{de::DeserializeOwned, Serialize};
+use std::iter::Sum;
+
+#[service]
+trait Statistics {
+    #[duty(instance(i64), instance(f64))]
+    fn sum<T>(&self, values: Vec<T>) -> T
+    where
+        T: Sum + Serialize + DeserializeOwned;
+
+    #[duty(instance(String, u32))]
+    fn pair<K, V>(&self, key: K, value: V) -> (K, Option<V>)
+    where
+        K: Serialize + DeserializeOwned,
+        V: Serialize + DeserializeOwned;
+}
+
+struct StatisticsServer;
+
+impl Statistics for StatisticsServer {
+    fn sum<T>(&self, values: Vec<T>) -> T
+    where
+        T: Sum + Serialize + DeserializeOwned,
+    {
+        values.into_iter().sum()
+    }
+
+    fn pair<K, V>(&self, key: K, value: V) -> (K, Option<V>)
+    where
+        K: Serialize + DeserializeOwned,
+        V: Serialize + DeserializeOwned,
+    {
+        (key, Some(value))
+    }
+}
+
+#[test]
+fn generic_methods() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+
+        s.spawn(|| -> Result<(), Error> {
+            let mut transport = transport::Json::new(server_stream);
+            for _ in 0..3 {
+                StatisticsServer.handle_next_request(&mut transport)?;
+            }
+            Ok(())
+        });
+
+        let client = StatisticsClient::new(transport::Json::new(client_stream))?;
+
+        assert_eq!(client.sum(vec![1i64, 2, 3])?, 6);
+        assert_eq!(client.sum(vec![0.5f64, 0.25])?, 0.75);
+        assert_eq!(
+            client.pair("answer".to_owned(), 42u32)?,
+            ("answer".to_owned(), Some(42))
+        );
+
+        Ok(())
+    })
+}
@@ -0,0 +1,106 @@
+use duty::error::Error;
+use duty::heartbeat::Heartbeat;
+use duty::{service, transport};
+use std::io::{self, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+const INTERVAL: Duration = Duration::from_millis(20);
+
+/// Leaves room for pongs delayed by a busy machine.
+const MAX_MISSED: u32 = 5;
+
+#[service]
+trait Sleeper {
+    fn sleep(&self, millis: u64) -> u64;
+}
+
+struct SleeperServer;
+
+impl Sleeper for SleeperServer {
+    fn sleep(&self, millis: u64) -> u64 {
+        std::thread::sleep(Duration::from_millis(millis));
+        millis
+    }
+}
+
+fn heartbeat_transport(stream: TcpStream) -> io::Result<transport::Bincode<Heartbeat<TcpStream>>> {
+    Ok(transport::Bincode::new(Heartbeat::with_interval(
+        stream, INTERVAL, MAX_MISSED,
+    )?))
+}
+
+#[test]
+fn slow_call_and_idle_client() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    std::thread::scope(|s| {
+        let server = s.spawn(|| -> Result<(), Error> {
+            let stream = listener.accept()?.0;
+            SleeperServer.serve(heartbeat_transport(stream)?)
+        });
+
+        let client = SleeperClient::new(heartbeat_transport(TcpStream::connect(addr)?)?)?;
+
+        // Server which is busy for many intervals still answers pings
+        assert_eq!(client.sleep(200)?, 200);
+
+        // So does client which doesn't call anything
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(client.sleep(0)?, 0);
+
+        drop(client);
+        server.join().expect("Thread panicked")
+    })
+}
+
+#[test]
+fn unresponsive_server() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let client = SleeperClient::new(heartbeat_transport(TcpStream::connect(
+        listener.local_addr()?,
+    )?)?)?;
+
+    // Server accepts the connection, but never answers
+    let _server_stream = listener.accept()?;
+
+    let error = client.sleep(0).unwrap_err();
+    assert!(matches!(error.root(), Error::Io(e) if e.kind() == io::ErrorKind::TimedOut));
+    assert!(error.is_retryable());
+
+    // Further calls fail right away
+    let error = client.sleep(0).unwrap_err();
+    assert!(matches!(error.root(), Error::Io(e) if e.kind() == io::ErrorKind::TimedOut));
+
+    Ok(())
+}
+
+#[test]
+fn stale_session_closed() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+
+    // Client connects, but never answers
+    let _client_stream = TcpStream::connect(listener.local_addr()?)?;
+
+    let stream = listener.accept()?.0;
+    let result = SleeperServer.serve(heartbeat_transport(stream)?);
+    assert!(matches!(result, Err(Error::Io(e)) if e.kind() == io::ErrorKind::TimedOut));
+
+    Ok(())
+}
+
+#[test]
+fn oversized_frame_rejected() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let mut client_stream = TcpStream::connect(listener.local_addr()?)?;
+
+    // Data frame claiming to be 4 GiB long
+    client_stream.write_all(&[0, 0xff, 0xff, 0xff, 0xff])?;
+
+    let stream = listener.accept()?.0;
+    let result = SleeperServer.serve(heartbeat_transport(stream)?);
+    assert!(matches!(result, Err(Error::Decode(_))), "{:?}", result);
+
+    Ok(())
+}
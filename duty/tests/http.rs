@@ -0,0 +1,225 @@
+#![cfg(feature = "http")]
+
+use duty::error::Error;
+use duty::http::{Format, HttpClient, HttpServer};
+use duty::{listener, service};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+#[service]
+trait InventoryService {
+    fn count(&self, item: String) -> u32;
+    fn total(&self) -> u32;
+}
+
+struct InventoryServiceServer;
+
+impl InventoryService for InventoryServiceServer {
+    fn count(&self, item: String) -> u32 {
+        match item.as_str() {
+            "apple" => 3,
+            "pear" => 5,
+            _ => 0,
+        }
+    }
+
+    fn total(&self) -> u32 {
+        8
+    }
+}
+
+fn start_server() -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    std::thread::spawn(move || {
+        listener::serve(&listener, HttpServer::new, |mut transport| loop {
+            InventoryServiceServer.handle_next_request(&mut transport)?;
+        })
+    });
+
+    Ok(addr)
+}
+
+/// Sends raw HTTP request and returns status code and body of the response.
+fn post(addr: SocketAddr, path: &str, content_type: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    // Sent at once, so that the server reads even the body it rejects
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, body.trim_end().to_owned())
+}
+
+#[test]
+fn http_client() -> Result<(), Error> {
+    let addr = start_server()?;
+
+    for format in [Format::Bincode, Format::Json] {
+        let transport = HttpClient::new(TcpStream::connect(addr)?).format(format);
+        let client = InventoryServiceClient::new(transport)?;
+
+        assert_eq!(client.count("apple".to_owned())?, 3);
+        assert_eq!(client.count("plum".to_owned())?, 0);
+        assert_eq!(client.total()?, 8);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn http_raw_requests() -> Result<(), Error> {
+    let addr = start_server()?;
+
+    assert_eq!(
+        post(
+            addr,
+            "/InventoryService/count",
+            "application/json",
+            r#"{"count": {"item": "pear"}}"#
+        ),
+        (200, "5".to_owned())
+    );
+    assert_eq!(
+        post(
+            addr,
+            "/InventoryService/total",
+            "application/json; charset=utf-8",
+            r#"{"total": {}}"#
+        ),
+        (200, "8".to_owned())
+    );
+
+    assert_eq!(
+        post(
+            addr,
+            "/InventoryService/sell",
+            "application/json",
+            r#"{"sell": {}}"#
+        )
+        .0,
+        404
+    );
+    assert_eq!(
+        post(addr, "/InventoryService", "application/json", "{}").0,
+        404
+    );
+    assert_eq!(
+        post(
+            addr,
+            "/InventoryService/count",
+            "application/json",
+            r#"{"count": {}}"#
+        )
+        .0,
+        400
+    );
+    assert_eq!(
+        post(addr, "/InventoryService/total", "text/plain", "total").0,
+        415
+    );
+
+    // Body calling another method than the path
+    assert_eq!(
+        post(
+            addr,
+            "/InventoryService/total",
+            "application/json",
+            r#"{"count": {"item": "pear"}}"#
+        )
+        .0,
+        400
+    );
+    assert_eq!(
+        post(
+            addr,
+            "/OtherService/total",
+            "application/json",
+            r#"{"total": {}}"#
+        )
+        .0,
+        404
+    );
+
+    Ok(())
+}
+
+#[test]
+fn http_body_too_large() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    std::thread::spawn(move || {
+        listener::serve(
+            &listener,
+            |stream| HttpServer::new(stream).max_body_len(16),
+            |mut transport| loop {
+                InventoryServiceServer.handle_next_request(&mut transport)?;
+            },
+        )
+    });
+
+    assert_eq!(
+        post(
+            addr,
+            "/InventoryService/total",
+            "application/json",
+            r#"{"total": {}}"#
+        ),
+        (200, "8".to_owned())
+    );
+    assert_eq!(
+        post(
+            addr,
+            "/InventoryService/count",
+            "application/json",
+            r#"{"count": {"item": "a very long name of the item"}}"#
+        )
+        .0,
+        413
+    );
+
+    Ok(())
+}
+
+#[test]
+fn http_error_status() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    std::thread::spawn(move || -> std::io::Result<()> {
+        let (mut stream, _) = listener.accept()?;
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request)?;
+        write!(
+            stream,
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 11\r\n\r\noverloaded\n"
+        )
+    });
+
+    let client = InventoryServiceClient::new(HttpClient::from_stream(TcpStream::connect(addr)?))?;
+    let error = client.total().unwrap_err();
+    let context = error.context().expect("error has call context");
+    assert_eq!(context.peer_addr, Some(addr.to_string()));
+    match error.root() {
+        Error::Http { status, body } => {
+            assert_eq!(*status, 503);
+            assert_eq!(body, "overloaded\n");
+        }
+        error => panic!("unexpected error {:?}", error),
+    }
+    assert!(error.is_retryable());
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use duty::dispatcher::Dispatcher;
+use duty::error::Error;
+use duty::inprocess::InProcess;
+use duty::procedure::Procedure;
+use duty::server::Server;
+use duty::service;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static SERIALIZED: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts how many times it was serialized.
+#[derive(Clone, Debug, PartialEq)]
+struct Matrix(Vec<Vec<f64>>);
+
+impl Serialize for Matrix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SERIALIZED.fetch_add(1, Ordering::SeqCst);
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Matrix {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Matrix)
+    }
+}
+
+#[service]
+trait MatrixService {
+    fn transpose(&self, matrix: Matrix) -> Matrix;
+    fn trace(&self, matrix: Matrix) -> f64;
+}
+
+struct MatrixServiceServer;
+
+impl MatrixService for MatrixServiceServer {
+    fn transpose(&self, matrix: Matrix) -> Matrix {
+        let rows = matrix.0.len();
+        let cols = matrix.0.first().map_or(0, Vec::len);
+        Matrix(
+            (0..cols)
+                .map(|c| (0..rows).map(|r| matrix.0[r][c]).collect())
+                .collect(),
+        )
+    }
+
+    fn trace(&self, matrix: Matrix) -> f64 {
+        (0..matrix.0.len()).map(|i| matrix.0[i][i]).sum()
+    }
+}
+
+#[test]
+fn inprocess_service() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_transport, mut server_transport) = InProcess::new_pair();
+
+        s.spawn(move || -> Result<(), Error> {
+            for _ in 0..2 {
+                MatrixServiceServer.handle_next_request(&mut server_transport)?;
+            }
+            Ok(())
+        });
+
+        let client = MatrixServiceClient::new(client_transport)?;
+        let matrix = Matrix(vec![vec![1.0, 2.0], vec![3.0, 4.0]]);
+
+        assert_eq!(
+            client.transpose(matrix.clone())?,
+            Matrix(vec![vec![1.0, 3.0], vec![2.0, 4.0]])
+        );
+        assert_eq!(client.trace(matrix)?, 5.0);
+
+        // Nothing went through serde
+        assert_eq!(SERIALIZED.load(Ordering::SeqCst), 0);
+
+        Ok(())
+    })
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct SumProc(Vec<u64>);
+
+impl Procedure for SumProc {
+    type Response = u64;
+    type Request = Self;
+
+    fn reduce(a: Self::Response, b: Self::Response) -> Self::Response {
+        a + b
+    }
+}
+
+#[test]
+fn inprocess_procedure() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let mut transports = Vec::new();
+
+        for _ in 0..4 {
+            let (client_transport, server_transport) = InProcess::new_pair();
+
+            s.spawn(|| -> Result<(), Error> {
+                let mut server = Server::<_, SumProc>::new(server_transport);
+                let (proc, handle) = server.next()?;
+                let sum = proc.0.iter().sum();
+                // Responding by reference falls back to serialization
+                handle.respond(&proc, &sum)
+            });
+
+            transports.push(client_transport);
+        }
+
+        let mut dispatcher = Dispatcher::new(transports);
+        assert_eq!(dispatcher.call(&SumProc(vec![1, 2, 3])).get()?, 24);
+
+        Ok(())
+    })
+}
@@ -0,0 +1,218 @@
+use duty::error::Error;
+use duty::jsonrpc::{JsonRpc, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, PARSE_ERROR};
+use duty::stream::MpscStream;
+use duty::{service, Transport};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+#[service]
+trait GeometryService {
+    fn area(&self, width: f64, height: f64) -> f64;
+    fn scale(&self, points: Vec<(f64, f64)>, factor: f64) -> Vec<(f64, f64)>;
+    fn origin() -> (f64, f64);
+}
+
+struct GeometryServiceServer;
+
+impl GeometryService for GeometryServiceServer {
+    fn area(&self, width: f64, height: f64) -> f64 {
+        width * height
+    }
+
+    fn scale(&self, points: Vec<(f64, f64)>, factor: f64) -> Vec<(f64, f64)> {
+        points
+            .into_iter()
+            .map(|(x, y)| (x * factor, y * factor))
+            .collect()
+    }
+
+    fn origin() -> (f64, f64) {
+        (0.0, 0.0)
+    }
+}
+
+#[test]
+fn jsonrpc_loopback() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+
+        s.spawn(|| -> Result<(), Error> {
+            let mut transport = JsonRpc::new(server_stream);
+            for _ in 0..3 {
+                GeometryServiceServer.handle_next_request(&mut transport)?;
+            }
+            Ok(())
+        });
+
+        let client = GeometryServiceClient::new(JsonRpc::new(client_stream))?;
+
+        assert_eq!(client.area(2.0, 3.5)?, 7.0);
+        assert_eq!(
+            client.scale(vec![(1.0, 2.0), (-1.0, 0.5)], 2.0)?,
+            vec![(2.0, 4.0), (-2.0, 1.0)]
+        );
+        assert_eq!(client.origin()?, (0.0, 0.0));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn jsonrpc_foreign_client() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    std::thread::scope(|s| {
+        s.spawn(|| -> Result<(), Error> {
+            let (stream, _) = listener.accept()?;
+            let mut transport = JsonRpc::new(stream);
+            // Only valid requests reach the service
+            for _ in 0..5 {
+                GeometryServiceServer.handle_next_request(&mut transport)?;
+            }
+            Ok(())
+        });
+
+        let stream = TcpStream::connect(addr)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let mut call = |request: &str| -> Value {
+            writeln!(&stream, "{}", request).unwrap();
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            serde_json::from_str(&line).unwrap()
+        };
+
+        assert_eq!(
+            call(
+                r#"{"jsonrpc": "2.0", "method": "area", "params": {"width": 2, "height": 4}, "id": 1}"#
+            ),
+            json!({"jsonrpc": "2.0", "result": 8.0, "id": 1})
+        );
+        assert_eq!(
+            call(r#"{"jsonrpc": "2.0", "method": "area", "params": [3, 3], "id": "sq"}"#),
+            json!({"jsonrpc": "2.0", "result": 9.0, "id": "sq"})
+        );
+        assert_eq!(
+            call(r#"{"jsonrpc": "2.0", "method": "origin", "id": 2}"#),
+            json!({"jsonrpc": "2.0", "result": [0.0, 0.0], "id": 2})
+        );
+
+        let error_code =
+            |response: Value| (response["error"]["code"].as_i64(), response["id"].clone());
+
+        assert_eq!(
+            error_code(call(r#"{"jsonrpc": "2.0", "method": "area""#)),
+            (Some(PARSE_ERROR), Value::Null)
+        );
+        assert_eq!(
+            error_code(call(r#"{"method": "area", "id": 3}"#)),
+            (Some(INVALID_REQUEST), json!(3))
+        );
+        assert_eq!(
+            error_code(call(r#"{"jsonrpc": "2.0", "method": "volume", "id": 4}"#)),
+            (Some(METHOD_NOT_FOUND), json!(4))
+        );
+        assert_eq!(
+            error_code(call(
+                r#"{"jsonrpc": "2.0", "method": "area", "params": {"width": 2}, "id": 5}"#
+            )),
+            (Some(INVALID_PARAMS), json!(5))
+        );
+
+        // Notification is handled without response
+        writeln!(
+            &stream,
+            r#"{{"jsonrpc": "2.0", "method": "area", "params": [1, 1]}}"#
+        )?;
+        assert_eq!(
+            call(r#"{"jsonrpc": "2.0", "method": "area", "params": [1, 2], "id": 6}"#),
+            json!({"jsonrpc": "2.0", "result": 2.0, "id": 6})
+        );
+
+        Ok(())
+    })
+}
+
+#[test]
+fn jsonrpc_request_header() -> Result<(), Error> {
+    let (mut client_stream, server_stream) = MpscStream::new_pair();
+    writeln!(
+        client_stream,
+        r#"{{"jsonrpc": "2.0", "method": "area", "params": [1, 2], "id": 7}}"#
+    )?;
+
+    let mut transport = JsonRpc::new(server_stream);
+    let request = transport.receive_request::<GeometryServiceRequest>()?;
+    assert_eq!(request.header.request_id, 7);
+    assert_eq!(request.header.service, "GeometryService");
+    assert_eq!(request.header.method, "area");
+    assert!(matches!(
+        request.body,
+        GeometryServiceRequest::Area { width, height } if width == 1.0 && height == 2.0
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn jsonrpc_remote_error() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    std::thread::scope(|s| {
+        s.spawn(|| -> std::io::Result<()> {
+            let (stream, _) = listener.accept()?;
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut line = String::new();
+            reader.read_line(&mut line)?;
+
+            let request: Value = serde_json::from_str(&line)?;
+            assert_eq!(request["method"], "area");
+            assert_eq!(request["params"], json!({"width": 1.0, "height": 2.0}));
+
+            let response = json!({
+                "jsonrpc": "2.0",
+                "error": {"code": -32000, "message": "overloaded"},
+                "id": request["id"],
+            });
+            writeln!(&stream, "{}", response)
+        });
+
+        let client = GeometryServiceClient::new(JsonRpc::from_stream(TcpStream::connect(addr)?))?;
+        let error = client.area(1.0, 2.0).unwrap_err();
+        match error.root() {
+            Error::Remote { code, message } => {
+                assert_eq!(*code, -32000);
+                assert_eq!(message, "overloaded");
+            }
+            error => panic!("unexpected error {:?}", error),
+        }
+        assert!(!error.is_retryable());
+
+        let context = error.context().expect("error has call context");
+        assert_eq!(context.service, "GeometryService");
+        assert_eq!(context.method, "area");
+        assert_eq!(context.peer_addr, Some(addr.to_string()));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn jsonrpc_line_too_long() -> Result<(), Error> {
+    let (mut client_stream, server_stream) = MpscStream::new_pair();
+    let mut transport = JsonRpc::new(server_stream).max_line_len(1000);
+
+    // Peer never finishes the line
+    std::thread::spawn(move || {
+        let chunk = [b' '; 100];
+        while client_stream.write_all(&chunk).is_ok() {}
+    });
+
+    let result = GeometryServiceServer.handle_next_request(&mut transport);
+    assert!(matches!(result, Err(Error::Decode(_))), "{:?}", result);
+
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use duty::error::Error;
+use duty::metrics::{self, PrometheusExporter, Side};
+use duty::stream::MpscStream;
+use duty::{service, transport};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+#[service]
+trait SumService {
+    fn sum(&self, values: Vec<f64>) -> f64;
+}
+
+struct SumServiceServer;
+
+impl SumService for SumServiceServer {
+    fn sum(&self, values: Vec<f64>) -> f64 {
+        values.iter().sum()
+    }
+}
+
+#[test]
+fn metrics() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+
+        s.spawn(|| -> Result<(), Error> {
+            let mut transport = transport::Bincode::new(server_stream);
+
+            let server = SumServiceServer;
+            for _ in 0..3 {
+                server.handle_next_request(&mut transport)?;
+            }
+            Ok(())
+        });
+
+        let transport = transport::Bincode::new(client_stream);
+        let client = SumServiceClient::new(transport)?;
+
+        assert_eq!(client.sum(vec![1.0, 2.0])?, 3.0);
+        assert_eq!(client.sum(vec![])?, 0.0);
+        assert_eq!(client.sum(vec![0.5; 10])?, 5.0);
+
+        Ok::<_, Error>(())
+    })?;
+
+    let snapshot = metrics::snapshot();
+    assert_eq!(snapshot.len(), 2);
+
+    let client = &snapshot[0];
+    let server = &snapshot[1];
+
+    assert_eq!(client.side, Side::Client);
+    assert_eq!(server.side, Side::Server);
+
+    for method in [client, server] {
+        assert_eq!(method.service, "SumService");
+        assert_eq!(method.method, "sum");
+        assert_eq!(method.calls, 3);
+        assert_eq!(method.errors, 0);
+        assert_eq!(method.latency.count, 3);
+    }
+
+    assert!(client.bytes_sent > 12 * 8);
+    assert_eq!(client.bytes_sent, server.bytes_received);
+    assert_eq!(client.bytes_received, server.bytes_sent);
+    assert_eq!(server.bytes_sent, 3 * 8);
+
+    let exporter = PrometheusExporter::bind("127.0.0.1:0").expect("Cannot bind exporter");
+    let addr = exporter.local_addr().expect("Missing exporter address");
+    exporter.spawn();
+
+    let mut stream = TcpStream::connect(addr).expect("Cannot connect to exporter");
+    stream
+        .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .expect("Cannot send HTTP request");
+    let mut response = String::new();
+    stream
+        .read_to_string(&mut response)
+        .expect("Cannot read HTTP response");
+
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response
+        .contains("duty_calls_total{side=\"client\",service=\"SumService\",method=\"sum\"} 3"));
+    assert!(response.contains(
+        "duty_latency_seconds_count{side=\"server\",service=\"SumService\",method=\"sum\"} 3"
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use duty::stream::MpscStream;
+use std::io::{ErrorKind, Read, Write};
+
+#[test]
+fn mpsc_stream_partial_read() -> std::io::Result<()> {
+    let (mut a, mut b) = MpscStream::new_pair();
+
+    a.write_all(b"abc")?;
+    let mut buf = [0; 16];
+    assert_eq!(b.read(&mut buf)?, 3);
+    assert_eq!(&buf[..3], b"abc");
+
+    b.write_all(b"reply")?;
+    let mut buf = [0; 2];
+    a.read_exact(&mut buf)?;
+    assert_eq!(&buf, b"re");
+    let mut buf = [0; 8];
+    assert_eq!(a.read(&mut buf)?, 3);
+    assert_eq!(&buf[..3], b"ply");
+
+    Ok(())
+}
+
+#[test]
+fn mpsc_stream_bounded() -> std::io::Result<()> {
+    let (mut a, mut b) = MpscStream::with_capacity(1024);
+    let data: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+
+    std::thread::scope(|s| {
+        let writer = s.spawn(|| -> std::io::Result<()> {
+            // Single write accepts at most the capacity
+            assert_eq!(a.write(&data)?, 1024);
+            a.write_all(&data[1024..])?;
+            a.close_write();
+            Ok(())
+        });
+
+        let mut received = Vec::new();
+        b.read_to_end(&mut received)?;
+        assert_eq!(received, data);
+
+        writer.join().expect("Thread panicked")
+    })
+}
+
+#[test]
+fn mpsc_stream_close() -> std::io::Result<()> {
+    let (mut a, mut b) = MpscStream::new_pair();
+
+    a.write_all(b"last words")?;
+    drop(a);
+
+    let mut received = String::new();
+    b.read_to_string(&mut received)?;
+    assert_eq!(received, "last words");
+    assert_eq!(b.read(&mut [0; 4])?, 0);
+
+    assert_eq!(
+        b.write(b"hello?").unwrap_err().kind(),
+        ErrorKind::BrokenPipe
+    );
+
+    Ok(())
+}
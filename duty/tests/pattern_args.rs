@@ -0,0 +1,70 @@
+use duty::error::Error;
+use duty::stream::MpscStream;
+use duty::{service, transport};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize)]
+struct Rect {
+    width: u32,
+    height: u32,
+}
+
+#[service]
+trait Geometry {
+    #[duty(rpc)]
+    fn area(&self, Rect { width, height }: Rect) -> u32 {
+        width * height
+    }
+
+    #[duty(rpc)]
+    fn swap(&self, (a, b): (i32, i32), mut offset: i32) -> (i32, i32) {
+        offset *= 2;
+        (b + offset, a + offset)
+    }
+
+    fn constant(&self, _: String) -> u32;
+
+    // Pattern argument next to one named like a generated name would be
+    #[duty(rpc)]
+    fn shift(&self, (a, b): (i32, i32), arg0: i32) -> (i32, i32) {
+        (a + arg0, b + arg0)
+    }
+}
+
+struct GeometryServer;
+
+impl Geometry for GeometryServer {
+    fn constant(&self, _: String) -> u32 {
+        7
+    }
+}
+
+#[test]
+fn pattern_args() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+
+        s.spawn(|| -> Result<(), Error> {
+            let mut transport = transport::Bincode::new(server_stream);
+            for _ in 0..4 {
+                GeometryServer.handle_next_request(&mut transport)?;
+            }
+            Ok(())
+        });
+
+        let client = GeometryClient::new(transport::Bincode::new(client_stream))?;
+
+        assert_eq!(
+            client.area(Rect {
+                width: 3,
+                height: 4
+            })?,
+            12
+        );
+        assert_eq!(client.swap((1, 2), 5)?, (12, 11));
+        assert_eq!(client.constant("ignored".to_owned())?, 7);
+        assert_eq!(client.shift((1, 2), 10)?, (11, 12));
+
+        Ok(())
+    })
+}
@@ -0,0 +1,50 @@
+#![cfg(unix)]
+
+use duty::error::Error;
+use duty::process::ProcessPool;
+use duty::transport::Bincode;
+use duty::Transport;
+use std::time::{Duration, Instant};
+
+#[test]
+fn process_pool() -> Result<(), Error> {
+    let mut workers = ProcessPool::new("cat")
+        .workers(3)
+        .spawn(Bincode::new)
+        .expect("Cannot spawn workers");
+
+    assert_eq!(workers.len(), 3);
+
+    for (i, worker) in workers.iter_mut().enumerate() {
+        assert_eq!(worker.index(), i);
+
+        let echo: String = worker.send_receive(&format!("hello {}", i))?;
+        assert_eq!(echo, format!("hello {}", i));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn process_pool_restart() {
+    let mut workers = ProcessPool::new("sh")
+        .args(["-c", "echo crashing >&2; exit 1"])
+        .workers(1)
+        .spawn(Bincode::new)
+        .expect("Cannot spawn workers");
+
+    let worker = &mut workers[0];
+    let pid = worker.pid();
+
+    // Process exits on its own, the next call starts it again
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while worker.try_wait().expect("Cannot check worker").is_none() {
+        assert!(Instant::now() < deadline, "worker didn't exit");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    assert!(worker.send_receive::<_, String>(&"hello").is_err());
+
+    assert_eq!(worker.restarts(), 1);
+    assert_ne!(worker.pid(), pid);
+}
@@ -45,10 +45,10 @@ fn raw_single_proc() -> Result<(), Error> {
 
         let mut client = Dispatcher::new(transports);
 
-        assert_eq!(client.call(&AndProc { a: true, b: true }).get()?, true);
-        assert_eq!(client.call(&AndProc { a: false, b: true }).get()?, false);
-        assert_eq!(client.call(&AndProc { a: true, b: false }).get()?, false);
-        assert_eq!(client.call(&AndProc { a: false, b: false }).get()?, false);
+        assert!(client.call(&AndProc { a: true, b: true }).get()?);
+        assert!(!client.call(&AndProc { a: false, b: true }).get()?);
+        assert!(!client.call(&AndProc { a: true, b: false }).get()?);
+        assert!(!client.call(&AndProc { a: false, b: false }).get()?);
 
         Ok(())
     })
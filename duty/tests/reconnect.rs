@@ -0,0 +1,165 @@
+use duty::envelope::Header;
+use duty::error::Error;
+use duty::reconnect::Reconnect;
+use duty::stream::MpscStream;
+use duty::{service, transport};
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[service]
+trait Counter {
+    #[duty(idempotent)]
+    fn get(&self) -> u32;
+
+    fn increment(&self) -> u32;
+}
+
+#[derive(Clone, Default)]
+struct CounterServer {
+    value: Arc<AtomicU32>,
+}
+
+impl Counter for CounterServer {
+    fn get(&self) -> u32 {
+        self.value.load(Ordering::SeqCst)
+    }
+
+    fn increment(&self) -> u32 {
+        self.value.fetch_add(1, Ordering::SeqCst) + 1
+    }
+}
+
+/// Connects to a new server thread each time. Server of the first connection
+/// goes away as soon as a request arrives, before answering it.
+fn connect_crashing_once(
+    server: CounterServer,
+    connections: Arc<AtomicUsize>,
+) -> impl FnMut() -> Result<transport::Bincode<MpscStream>, Error> + Send + 'static {
+    move || {
+        let (client_stream, mut server_stream) = MpscStream::new_pair();
+
+        if connections.fetch_add(1, Ordering::SeqCst) == 0 {
+            std::thread::spawn(move || server_stream.read(&mut [0]));
+        } else {
+            let server = server.clone();
+            std::thread::spawn(move || server.serve(transport::Bincode::new(server_stream)));
+        }
+
+        Ok(transport::Bincode::new(client_stream))
+    }
+}
+
+#[test]
+fn idempotent_call_replayed() -> Result<(), Error> {
+    let connections = Arc::new(AtomicUsize::new(0));
+    let transport = Reconnect::new(connect_crashing_once(
+        CounterServer::default(),
+        connections.clone(),
+    ))
+    .initial_delay(Duration::from_millis(1));
+
+    let client = CounterClient::new(transport)?;
+    assert_eq!(client.get()?, 0);
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[test]
+fn other_call_fails_and_reconnects() -> Result<(), Error> {
+    let server = CounterServer::default();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let transport = Reconnect::new(connect_crashing_once(server.clone(), connections.clone()));
+
+    let client = CounterClient::new(transport)?;
+    let error = client.increment().unwrap_err();
+    assert!(error.is_retryable());
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    // Next call goes over a new connection
+    assert_eq!(client.increment()?, 1);
+    assert_eq!(client.get()?, 1);
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[test]
+fn connect_with_backoff() -> Result<(), Error> {
+    let server = CounterServer::default();
+    let mut attempts = 0;
+
+    let transport = Reconnect::new(move || {
+        attempts += 1;
+        if attempts < 3 {
+            return Err(io::Error::from(io::ErrorKind::ConnectionRefused).into());
+        }
+
+        let (client_stream, server_stream) = MpscStream::new_pair();
+        let server = server.clone();
+        std::thread::spawn(move || server.serve(transport::Bincode::new(server_stream)));
+        Ok(transport::Bincode::new(client_stream))
+    })
+    .initial_delay(Duration::from_millis(1));
+
+    let client = CounterClient::new(transport)?;
+    assert_eq!(client.increment()?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn connect_gives_up() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+
+    let transport = Reconnect::new({
+        let attempts = attempts.clone();
+        move || -> Result<transport::Bincode<MpscStream>, Error> {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(io::Error::from(io::ErrorKind::ConnectionRefused).into())
+        }
+    })
+    .initial_delay(Duration::from_millis(1))
+    .max_attempts(4);
+
+    let client = CounterClient::new(transport).unwrap();
+    let error = client.get().unwrap_err();
+    assert!(matches!(error.root(), Error::Io(e) if e.kind() == io::ErrorKind::ConnectionRefused));
+    assert_eq!(attempts.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn replay_shares_attempts_with_connecting() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+
+    // Every other attempt is refused, the others reach a server which goes
+    // away without answering
+    let transport = Reconnect::new({
+        let attempts = attempts.clone();
+        move || -> Result<transport::Bincode<MpscStream>, Error> {
+            if attempts.fetch_add(1, Ordering::SeqCst) % 2 == 1 {
+                return Err(io::Error::from(io::ErrorKind::ConnectionRefused).into());
+            }
+
+            let (client_stream, mut server_stream) = MpscStream::new_pair();
+            std::thread::spawn(move || server_stream.read(&mut [0]));
+            Ok(transport::Bincode::new(client_stream))
+        }
+    })
+    .initial_delay(Duration::from_millis(1))
+    .max_attempts(4);
+
+    let client = CounterClient::new(transport).unwrap();
+    let error = client.get().unwrap_err();
+    assert!(matches!(error.root(), Error::Io(e) if e.kind() == io::ErrorKind::ConnectionRefused));
+    assert_eq!(attempts.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn idempotent_flag_not_sent() {
+    let header = Header::new(1, "Counter", "get");
+    let encoded = bincode::serialize(&header).unwrap();
+    assert_eq!(bincode::serialize(&header.idempotent()).unwrap(), encoded);
+}
@@ -0,0 +1,166 @@
+use duty::error::Error;
+use duty::listener::{self, Listener, ServerBuilder, Shutdown};
+use duty::stream::MpscStream;
+use duty::{service, transport};
+use std::io::{self, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+#[service]
+trait Echo {
+    fn echo(&self, text: String) -> String;
+}
+
+#[derive(Clone)]
+struct EchoServer;
+
+impl Echo for EchoServer {
+    fn echo(&self, text: String) -> String {
+        text
+    }
+}
+
+#[test]
+fn serve_until_disconnect() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+
+        let server = s.spawn(|| EchoServer.serve(transport::Bincode::new(server_stream)));
+
+        let client = EchoClient::new(transport::Bincode::new(client_stream))?;
+        assert_eq!(client.echo("one".to_owned())?, "one");
+        assert_eq!(client.echo("two".to_owned())?, "two");
+        drop(client);
+
+        // Clean disconnect is not an error
+        server.join().expect("Thread panicked")
+    })
+}
+
+#[test]
+fn serve_broken_message() {
+    let (mut client_stream, server_stream) = MpscStream::new_pair();
+
+    client_stream.write_all(&[1, 2, 3]).unwrap();
+    drop(client_stream);
+
+    // Connection closed in the middle of a message is an error
+    let result = EchoServer.serve(transport::Bincode::new(server_stream));
+    assert!(matches!(result, Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof));
+}
+
+#[test]
+fn serve_with_builder() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    let shutdown = Shutdown::new();
+    let connected = Arc::new(AtomicUsize::new(0));
+    let (disconnected_sender, disconnected) = mpsc::channel();
+
+    let builder = ServerBuilder::new()
+        .max_connections(2)
+        .shutdown(&shutdown)
+        .on_connect({
+            let connected = connected.clone();
+            move |_| {
+                connected.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+        .on_disconnect(move |connection, result| {
+            let clean = result.is_ok();
+            disconnected_sender
+                .send((connection, clean))
+                .expect("test is waiting");
+        });
+
+    std::thread::scope(|s| {
+        let server =
+            s.spawn(|| EchoServer.serve_with(&builder, &listener, transport::Bincode::new));
+
+        for i in 0..3 {
+            let client = EchoClient::new(transport::Bincode::new(TcpStream::connect(addr)?))?;
+            assert_eq!(client.echo(format!("hello {}", i))?, format!("hello {}", i));
+        }
+
+        let mut ended: Vec<_> = (0..3).map(|_| disconnected.recv().unwrap()).collect();
+        ended.sort();
+        assert_eq!(ended, [(0, true), (1, true), (2, true)]);
+        assert_eq!(connected.load(Ordering::SeqCst), 3);
+
+        shutdown.trigger();
+        server.join().expect("Thread panicked")?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn shutdown_with_all_connections_busy() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    let shutdown = Shutdown::new();
+    let (connected_sender, connected) = mpsc::channel();
+    let builder = ServerBuilder::new()
+        .max_connections(1)
+        .shutdown(&shutdown)
+        .on_connect(move |connection| {
+            connected_sender.send(connection).expect("test is waiting");
+        });
+
+    std::thread::scope(|s| {
+        let server =
+            s.spawn(|| EchoServer.serve_with(&builder, &listener, transport::Bincode::new));
+
+        // Connection which stays open takes the only slot
+        let client = EchoClient::new(transport::Bincode::new(TcpStream::connect(addr)?))?;
+        assert_eq!(client.echo("busy".to_owned())?, "busy");
+        assert_eq!(connected.recv().unwrap(), 0);
+
+        shutdown.trigger();
+        server.join().expect("Thread panicked")?;
+
+        // Connection being served is left to end on its own
+        assert_eq!(client.echo("still busy".to_owned())?, "still busy");
+
+        Ok(())
+    })
+}
+
+/// Listener returning prepared results of accepting.
+struct ScriptedListener(Mutex<Vec<io::Result<MpscStream>>>);
+
+impl Listener for ScriptedListener {
+    type Stream = MpscStream;
+
+    fn accept(&self) -> io::Result<MpscStream> {
+        self.0.lock().unwrap().remove(0)
+    }
+}
+
+#[test]
+fn serve_after_transient_errors() -> Result<(), Error> {
+    let (client_stream, server_stream) = MpscStream::new_pair();
+    let listener = ScriptedListener(Mutex::new(vec![
+        Err(io::ErrorKind::ConnectionAborted.into()),
+        Err(io::ErrorKind::Interrupted.into()),
+        Ok(server_stream),
+        Err(io::ErrorKind::PermissionDenied.into()),
+    ]));
+
+    let client = EchoClient::new(transport::Bincode::new(client_stream))?;
+
+    std::thread::scope(|s| {
+        s.spawn(move || assert_eq!(client.echo("still here".to_owned()).unwrap(), "still here"));
+
+        // Only the permanent error ends serving
+        let result = listener::serve(&listener, transport::Bincode::new, |transport| {
+            EchoServer.serve(transport)
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    });
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use duty::error::Error;
+use duty::stream::MpscStream;
+
+/// Stands for a crate re-exporting duty under a different path.
+mod facade {
+    pub use duty as rpc;
+}
+
+use facade::rpc::{service, transport};
+
+#[service(client = Remote, request = Command, derive(Debug, Clone, PartialEq), crate = "crate::facade::rpc")]
+trait Counter {
+    #[duty(rename = "increment")]
+    fn add(&mut self, value: u64) -> u64;
+
+    fn get(&self) -> u64;
+
+    #[duty(skip)]
+    fn is_zero(&self) -> bool {
+        self.get() == 0
+    }
+}
+
+#[derive(Default)]
+struct CounterServer(u64);
+
+impl Counter for CounterServer {
+    fn add(&mut self, value: u64) -> u64 {
+        self.0 += value;
+        self.0
+    }
+
+    fn get(&self) -> u64 {
+        self.0
+    }
+}
+
+#[test]
+fn service_attrs() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+
+        s.spawn(|| -> Result<(), Error> {
+            let mut transport = transport::Json::new(server_stream);
+            let mut server = CounterServer::default();
+            assert!(server.is_zero());
+            for _ in 0..3 {
+                server.handle_next_request(&mut transport)?;
+            }
+            Ok(())
+        });
+
+        let client = Remote::new(transport::Json::new(client_stream))?;
+
+        assert_eq!(client.add(2)?, 2);
+        assert_eq!(client.add(3)?, 5);
+        assert_eq!(client.get()?, 5);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn service_attrs_request() {
+    let command = Command::Add { value: 1 };
+    assert_eq!(command.clone(), command);
+    assert_eq!(format!("{:?}", command), "Add { value: 1 }");
+    assert_eq!(
+        serde_json::to_string(&command).unwrap(),
+        r#"{"increment":{"value":1}}"#
+    );
+}
@@ -0,0 +1,107 @@
+#![cfg(all(feature = "shm", target_os = "linux"))]
+
+use duty::error::Error;
+use duty::service;
+use duty::shm::ShmStream;
+use duty::transport::Bincode;
+use std::io::{Read, Write};
+
+#[service]
+trait VectorService {
+    fn norm(&self, values: Vec<f64>) -> f64;
+    fn scale(&self, values: Vec<f64>, factor: f64) -> Vec<f64>;
+}
+
+struct VectorServiceServer;
+
+impl VectorService for VectorServiceServer {
+    fn norm(&self, values: Vec<f64>) -> f64 {
+        values.iter().map(|v| v * v).sum::<f64>().sqrt()
+    }
+
+    fn scale(&self, values: Vec<f64>, factor: f64) -> Vec<f64> {
+        values.into_iter().map(|v| v * factor).collect()
+    }
+}
+
+fn shm_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("duty-shm-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn shm_large_messages() -> Result<(), Error> {
+    let path = shm_path("large");
+    // Much smaller than the messages, so both sides block on full ring
+    let client_stream = ShmStream::create(&path, 4096)?;
+    let server_stream = ShmStream::open(&path)?;
+    std::fs::remove_file(&path)?;
+
+    std::thread::scope(|s| {
+        s.spawn(|| -> Result<(), Error> {
+            let mut transport = Bincode::new(server_stream);
+            for _ in 0..2 {
+                VectorServiceServer.handle_next_request(&mut transport)?;
+            }
+            Ok(())
+        });
+
+        let client = VectorServiceClient::new(Bincode::new(client_stream))?;
+
+        let values: Vec<f64> = (0..100_000).map(f64::from).collect();
+        let scaled = client.scale(values.clone(), 0.5)?;
+        assert_eq!(scaled.len(), values.len());
+        assert!(scaled.iter().zip(&values).all(|(s, v)| *s == v * 0.5));
+
+        assert_eq!(client.norm(vec![3.0, 4.0])?, 5.0);
+
+        Ok(())
+    })
+}
+
+#[test]
+fn shm_memfd() -> Result<(), Error> {
+    let (mut stream, file) = ShmStream::memfd("duty-test", 10_000)?;
+    assert_eq!(stream.capacity(), 16384);
+
+    let mut peer = ShmStream::from_file(&file)?;
+    assert!(ShmStream::from_file(&file).is_err());
+
+    stream.write_all(b"ping")?;
+    let mut buf = [0; 16];
+    assert_eq!(peer.read(&mut buf)?, 4);
+    assert_eq!(&buf[..4], b"ping");
+
+    peer.write_all(b"pong")?;
+    drop(peer);
+
+    // Remaining data is still readable after the peer is gone
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest)?;
+    assert_eq!(rest, b"pong");
+
+    assert_eq!(
+        stream.write(b"late").unwrap_err().kind(),
+        std::io::ErrorKind::BrokenPipe
+    );
+
+    Ok(())
+}
+
+#[test]
+fn shm_rejects_invalid_capacity() -> Result<(), Error> {
+    let path = shm_path("zero-capacity");
+
+    // Initialized header claiming rings of no bytes
+    let mut header = vec![0; 8192];
+    header[..4].copy_from_slice(&0x6475_7479u32.to_ne_bytes());
+    std::fs::write(&path, &header)?;
+
+    let result = ShmStream::open(&path);
+    std::fs::remove_file(&path)?;
+    assert_eq!(
+        result.err().map(|e| e.kind()),
+        Some(std::io::ErrorKind::InvalidData)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,259 @@
+#![cfg(all(feature = "ssh", unix))]
+
+//! Runs against a throwaway `sshd` listening on the loopback, with its own
+//! host key, client key and configuration. Tests needing it are skipped when
+//! `sshd` is not installed.
+
+use duty::ssh::{Auth, HostKeyCheck, SshSession};
+use duty::transport::Bincode;
+use duty::Transport;
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+struct Sshd {
+    dir: PathBuf,
+    port: u16,
+    process: Child,
+}
+
+impl Sshd {
+    fn start(name: &str) -> Option<Sshd> {
+        let sshd = ["/usr/sbin/sshd", "/usr/bin/sshd", "/usr/local/sbin/sshd"]
+            .into_iter()
+            .map(Path::new)
+            .find(|path| path.exists());
+
+        let Some(sshd) = sshd else {
+            eprintln!("sshd not found, skipping");
+            return None;
+        };
+
+        let dir = std::env::temp_dir().join(format!("duty-ssh-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+
+        keygen(&dir.join("host_key"));
+        keygen(&dir.join("user_key"));
+        fs::copy(dir.join("user_key.pub"), dir.join("authorized_keys")).unwrap();
+
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        fs::write(
+            dir.join("sshd_config"),
+            format!(
+                "Port {port}\n\
+                 ListenAddress 127.0.0.1\n\
+                 HostKey {dir}/host_key\n\
+                 AuthorizedKeysFile {dir}/authorized_keys\n\
+                 PidFile none\n\
+                 StrictModes no\n\
+                 UsePAM no\n\
+                 PasswordAuthentication no\n",
+                port = port,
+                dir = dir.display()
+            ),
+        )
+        .unwrap();
+
+        let host_key = fs::read_to_string(dir.join("host_key.pub")).unwrap();
+        fs::write(
+            dir.join("known_hosts"),
+            format!("[127.0.0.1]:{} {}", port, host_key),
+        )
+        .unwrap();
+
+        let process = Command::new(sshd)
+            .args(["-D", "-e", "-f"])
+            .arg(dir.join("sshd_config"))
+            .spawn()
+            .unwrap();
+
+        let sshd = Sshd { dir, port, process };
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(Instant::now() < deadline, "sshd didn't start");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        Some(sshd)
+    }
+
+    fn host(&self) -> String {
+        format!("127.0.0.1:{}", self.port)
+    }
+
+    fn key_auth(&self) -> Auth {
+        Auth::KeyFile {
+            private_key: self.dir.join("user_key"),
+            public_key: Some(self.dir.join("user_key.pub")),
+            passphrase: None,
+        }
+    }
+}
+
+impl Drop for Sshd {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn keygen(path: &Path) {
+    let status = Command::new("ssh-keygen")
+        .args(["-q", "-t", "rsa", "-b", "2048", "-m", "PEM", "-N", "", "-f"])
+        .arg(path)
+        .status()
+        .expect("Cannot run ssh-keygen");
+    assert!(status.success());
+}
+
+fn user() -> String {
+    std::env::var("USER").unwrap_or_else(|_| "root".to_owned())
+}
+
+#[test]
+fn ssh_exec() {
+    let Some(sshd) = Sshd::start("exec") else {
+        return;
+    };
+
+    let session = SshSession::builder()
+        .user(user())
+        .auth(sshd.key_auth())
+        .host_key_check(HostKeyCheck::KnownHosts(sshd.dir.join("known_hosts")))
+        .keepalive(Duration::from_secs(1))
+        .connect(&sshd.host())
+        .expect("Cannot connect");
+
+    let mut transport = Bincode::new(session.exec("cat").expect("Cannot exec"));
+    let echo: String = transport.send_receive(&"hello").unwrap();
+    assert_eq!(echo, "hello");
+}
+
+#[test]
+fn ssh_keepalive_during_read() {
+    let Some(sshd) = Sshd::start("keepalive") else {
+        return;
+    };
+
+    let builder = SshSession::builder()
+        .user(user())
+        .auth(sshd.key_auth())
+        .host_key_check(HostKeyCheck::AcceptAny)
+        .keepalive(Duration::from_secs(1));
+
+    // Keepalives are sent while the response is awaited for several intervals
+    let session = builder.connect(&sshd.host()).expect("Cannot connect");
+    let mut transport = Bincode::new(session.exec("sleep 3; cat").expect("Cannot exec"));
+    let echo: String = transport.send_receive(&"hello").unwrap();
+    assert_eq!(echo, "hello");
+
+    // Timeout still ends the read
+    let session = builder
+        .timeout(Duration::from_secs(2))
+        .connect(&sshd.host())
+        .expect("Cannot connect");
+    let mut transport = Bincode::new(session.exec("sleep 5; cat").expect("Cannot exec"));
+    let started = Instant::now();
+    assert!(transport.send_receive::<_, String>(&"hello").is_err());
+    assert!(started.elapsed() < Duration::from_secs(4));
+}
+
+#[test]
+fn ssh_launch() {
+    let Some(sshd) = Sshd::start("launch") else {
+        return;
+    };
+
+    // Worker is started on every host
+    SshSession::builder()
+        .user(user())
+        .auth(sshd.key_auth())
+        .host_key_check(HostKeyCheck::AcceptAny)
+        .launch([sshd.host(), sshd.host()], "cat", Bincode::from_stream)
+        .expect("Cannot launch");
+
+    // Launching fails as a whole if any host fails
+    let result = SshSession::builder()
+        .user(user())
+        .auth(sshd.key_auth())
+        .host_key_check(HostKeyCheck::AcceptAny)
+        .launch([sshd.host(), "127.0.0.1:1".to_owned()], "cat", Bincode::new);
+    assert!(result.is_err());
+}
+
+#[test]
+fn ssh_rejects_unknown_host() {
+    let Some(sshd) = Sshd::start("unknown-host") else {
+        return;
+    };
+
+    let empty = sshd.dir.join("empty_known_hosts");
+    fs::write(&empty, "").unwrap();
+
+    let result = SshSession::builder()
+        .user(user())
+        .auth(sshd.key_auth())
+        .host_key_check(HostKeyCheck::KnownHosts(empty))
+        .connect(&sshd.host());
+    assert!(result.is_err());
+}
+
+#[test]
+fn ssh_rejects_wrong_key() {
+    let Some(sshd) = Sshd::start("wrong-key") else {
+        return;
+    };
+
+    keygen(&sshd.dir.join("other_key"));
+
+    let result = SshSession::builder()
+        .user(user())
+        .auth(Auth::KeyFile {
+            private_key: sshd.dir.join("other_key"),
+            public_key: Some(sshd.dir.join("other_key.pub")),
+            passphrase: None,
+        })
+        .host_key_check(HostKeyCheck::AcceptAny)
+        .connect(&sshd.host());
+    assert_eq!(
+        result.err().map(|e| e.kind()),
+        Some(std::io::ErrorKind::PermissionDenied)
+    );
+}
+
+#[test]
+fn ssh_ipv6_host() {
+    let listener = match TcpListener::bind("[::1]:0") {
+        Ok(listener) => listener,
+        Err(_) => {
+            eprintln!("IPv6 loopback not available, skipping");
+            return;
+        }
+    };
+    let port = listener.local_addr().unwrap().port();
+
+    let accepted = std::thread::spawn(move || listener.accept().is_ok());
+
+    // Not an SSH server, but the address must be parsed and connected to
+    let result = SshSession::builder()
+        .timeout(Duration::from_secs(1))
+        .host_key_check(HostKeyCheck::AcceptAny)
+        .connect(&format!("[::1]:{}", port));
+    assert!(result.is_err());
+    assert!(accepted.join().unwrap());
+
+    let result = SshSession::builder().connect("[::1");
+    assert_eq!(
+        result.err().map(|e| e.kind()),
+        Some(std::io::ErrorKind::InvalidInput)
+    );
+}
@@ -0,0 +1,168 @@
+#![cfg(feature = "tls")]
+
+use duty::error::Error;
+use duty::procedure::Procedure;
+use duty::server::Server;
+use duty::tls::{self, TlsAcceptor, TlsConnector};
+use duty::transport::Bincode;
+use duty::{service, Transport};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct WhoAmIProc;
+
+impl Procedure for WhoAmIProc {
+    type Response = Option<Vec<u8>>;
+    type Request = Self;
+
+    fn reduce(a: Self::Response, _b: Self::Response) -> Self::Response {
+        a
+    }
+}
+
+#[service]
+trait Echo {
+    fn echo(&self, text: String) -> String;
+}
+
+struct EchoServer;
+
+impl Echo for EchoServer {
+    fn echo(&self, text: String) -> String {
+        text
+    }
+}
+
+struct Pki {
+    ca: Certificate,
+    ca_key: KeyPair,
+}
+
+impl Pki {
+    fn new() -> Pki {
+        let mut params = CertificateParams::new(Vec::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate().unwrap();
+        let ca = params.self_signed(&ca_key).unwrap();
+        Pki { ca, ca_key }
+    }
+
+    fn issue(&self, name: &str, usage: ExtendedKeyUsagePurpose) -> (Certificate, KeyPair) {
+        let mut params = CertificateParams::new(vec![name.to_owned()]).unwrap();
+        params.extended_key_usages = vec![usage];
+        let key = KeyPair::generate().unwrap();
+        let cert = params.signed_by(&key, &self.ca, &self.ca_key).unwrap();
+        (cert, key)
+    }
+}
+
+/// PEM file removed when dropped.
+struct PemFile(PathBuf);
+
+impl AsRef<Path> for PemFile {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for PemFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+fn write_pem(name: &str, pem: &str) -> PemFile {
+    let path = std::env::temp_dir().join(format!("duty-tls-{}-{}.pem", std::process::id(), name));
+    std::fs::write(&path, pem).unwrap();
+    PemFile(path)
+}
+
+#[test]
+fn tls_mutual_auth() -> Result<(), Error> {
+    let pki = Pki::new();
+    let (server_cert, server_key) = pki.issue("localhost", ExtendedKeyUsagePurpose::ServerAuth);
+    let (client_cert, client_key) = pki.issue("client", ExtendedKeyUsagePurpose::ClientAuth);
+
+    let roots = tls::root_store(tls::load_certs(write_pem("ca", &pki.ca.pem()))?)?;
+
+    let acceptor = TlsAcceptor::with_client_auth(
+        tls::load_certs(write_pem("server-cert", &server_cert.pem()))?,
+        tls::load_private_key(write_pem("server-key", &server_key.serialize_pem()))?,
+        roots.clone(),
+    )?;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    std::thread::scope(|s| {
+        let server = s.spawn(|| -> Result<(), Error> {
+            // Client without certificate is rejected
+            let (stream, _) = listener.accept()?;
+            assert!(acceptor.accept(stream).is_err());
+
+            let (stream, _) = listener.accept()?;
+            let stream = acceptor.accept(stream)?;
+            let mut server = Server::<_, WhoAmIProc>::new(Bincode::from_tls(stream));
+
+            let (proc, handle) = server.next()?;
+            let certificates = handle.peer_identity().map(|i| i.certificates[0].clone());
+            handle.respond(&proc, &certificates)
+        });
+
+        let anonymous = TlsConnector::new(roots.clone());
+        let _ = anonymous.connect("localhost", TcpStream::connect(addr)?);
+
+        let connector = TlsConnector::with_client_auth(
+            roots,
+            vec![client_cert.der().clone()],
+            client_key.serialize_der().try_into().unwrap(),
+        )?;
+        let stream = connector.connect("localhost", TcpStream::connect(addr)?)?;
+
+        let transport = Bincode::from_tls(stream);
+        assert_eq!(transport.peer_addr(), Some(addr.to_string().as_str()));
+        let server_identity = transport.peer_identity().expect("server certificate");
+        assert_eq!(server_identity.certificates[0], server_cert.der().as_ref());
+
+        let mut dispatcher = duty::dispatcher::Dispatcher::new([transport]);
+        let certificate = dispatcher.call(&WhoAmIProc).get()?;
+        assert_eq!(certificate.as_deref(), Some(client_cert.der().as_ref()));
+
+        server.join().expect("Thread panicked")
+    })
+}
+
+#[test]
+fn tls_serve_ends_on_disconnect() -> Result<(), Error> {
+    let pki = Pki::new();
+    let (server_cert, server_key) = pki.issue("localhost", ExtendedKeyUsagePurpose::ServerAuth);
+    let roots = tls::root_store(tls::load_certs(write_pem("echo-ca", &pki.ca.pem()))?)?;
+
+    let acceptor = TlsAcceptor::new(
+        tls::load_certs(write_pem("echo-cert", &server_cert.pem()))?,
+        tls::load_private_key(write_pem("echo-key", &server_key.serialize_pem()))?,
+    )?;
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    std::thread::scope(|s| {
+        let server = s.spawn(|| -> Result<(), Error> {
+            let stream = acceptor.accept(listener.accept()?.0)?;
+            EchoServer.serve(Bincode::from_tls(stream))
+        });
+
+        let stream = TlsConnector::new(roots).connect("localhost", TcpStream::connect(addr)?)?;
+        let client = EchoClient::new(Bincode::from_tls(stream))?;
+        assert_eq!(client.echo("hello".to_owned())?, "hello");
+
+        // Dropping the client closes the connection without close_notify,
+        // which still ends serving without an error
+        drop(client);
+        server.join().expect("Thread panicked")
+    })
+}
@@ -0,0 +1,48 @@
+use duty::error::Error;
+use duty::stream::MpscStream;
+use duty::trace::TraceContext;
+use duty::{service, transport};
+
+#[service]
+trait TraceService {
+    fn trace_id(&self) -> Option<u64>;
+}
+
+struct TraceServiceServer;
+
+impl TraceService for TraceServiceServer {
+    fn trace_id(&self) -> Option<u64> {
+        TraceContext::current().map(|context| context.trace_id)
+    }
+}
+
+#[test]
+fn trace_propagation() -> Result<(), Error> {
+    std::thread::scope(|s| {
+        let (client_stream, server_stream) = MpscStream::new_pair();
+
+        s.spawn(|| -> Result<(), Error> {
+            let mut transport = transport::Bincode::new(server_stream);
+
+            let server = TraceServiceServer;
+            for _ in 0..2 {
+                server.handle_next_request(&mut transport)?;
+            }
+            Ok(())
+        });
+
+        let transport = transport::Bincode::new(client_stream);
+        let client = TraceServiceClient::new(transport)?;
+
+        let root = TraceContext::new_root();
+        {
+            let _guard = root.enter();
+            assert_eq!(client.trace_id()?, Some(root.trace_id));
+        }
+
+        assert_ne!(client.trace_id()?, Some(root.trace_id));
+        assert_eq!(TraceContext::current(), None);
+
+        Ok(())
+    })
+}
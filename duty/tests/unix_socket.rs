@@ -0,0 +1,137 @@
+#![cfg(unix)]
+
+use duty::error::Error;
+use duty::listener::{self, ServerBuilder, Shutdown};
+use duty::unix::{self, NamedPipe, UnixSocketListener};
+use duty::{service, transport};
+
+#[service]
+trait EchoService {
+    fn echo(&self, message: String) -> String;
+}
+
+#[derive(Clone)]
+struct EchoServiceServer;
+
+impl EchoService for EchoServiceServer {
+    fn echo(&self, message: String) -> String {
+        message
+    }
+}
+
+fn serve(listener: UnixSocketListener) {
+    std::thread::spawn(move || {
+        listener::serve(&listener, transport::Bincode::new, |mut transport| {
+            let server = EchoServiceServer;
+            loop {
+                server.handle_next_request(&mut transport)?;
+            }
+        })
+    });
+}
+
+#[test]
+fn unix_socket() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("duty-test-{}.sock", std::process::id()));
+
+    let listener = UnixSocketListener::bind(&path).expect("Cannot bind socket");
+    assert!(path.exists());
+    serve(listener);
+
+    for _ in 0..3 {
+        let stream = unix::connect(&path).expect("Cannot connect to socket");
+        let client = EchoServiceClient::new(transport::Bincode::new(stream))?;
+
+        assert_eq!(client.echo("hello".to_owned())?, "hello");
+        assert_eq!(client.echo("world".to_owned())?, "world");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn unix_socket_shutdown() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("duty-shutdown-{}.sock", std::process::id()));
+    let listener = UnixSocketListener::bind(&path).expect("Cannot bind socket");
+
+    let shutdown = Shutdown::new();
+    let builder = ServerBuilder::new().shutdown(&shutdown);
+
+    std::thread::scope(|s| {
+        let server =
+            s.spawn(|| EchoServiceServer.serve_with(&builder, &listener, transport::Bincode::new));
+
+        let stream = unix::connect(&path).expect("Cannot connect to socket");
+        let client = EchoServiceClient::new(transport::Bincode::new(stream))?;
+        assert_eq!(client.echo("hello".to_owned())?, "hello");
+        drop(client);
+
+        // Server blocked in accepting the next connection stops
+        shutdown.trigger();
+        server.join().expect("Thread panicked")?;
+
+        Ok(())
+    })
+}
+
+#[test]
+fn unix_socket_cleanup() {
+    let path = std::env::temp_dir().join(format!("duty-test-{}-cleanup.sock", std::process::id()));
+
+    let listener = UnixSocketListener::bind(&path).expect("Cannot bind socket");
+    assert!(UnixSocketListener::bind(&path).is_err());
+
+    drop(listener);
+    assert!(!path.exists());
+
+    // Stale socket file is replaced
+    drop(std::os::unix::net::UnixListener::bind(&path).expect("Cannot bind socket"));
+    assert!(path.exists());
+    drop(UnixSocketListener::bind(&path).expect("Cannot bind socket"));
+    assert!(!path.exists());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn unix_socket_abstract() -> Result<(), Error> {
+    let name = format!("duty-test-{}", std::process::id());
+
+    serve(UnixSocketListener::bind_abstract(&name).expect("Cannot bind socket"));
+
+    let stream = unix::connect_abstract(&name).expect("Cannot connect to socket");
+    let client = EchoServiceClient::new(transport::Bincode::new(stream))?;
+
+    assert_eq!(client.echo("abstract".to_owned())?, "abstract");
+
+    Ok(())
+}
+
+#[test]
+fn named_pipe() -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("duty-test-{}.pipe", std::process::id()));
+
+    std::thread::scope(|s| {
+        let server = s.spawn(|| -> Result<(), Error> {
+            let pipe = NamedPipe::create(&path)?;
+            EchoServiceServer.serve(transport::Bincode::new(pipe))
+        });
+
+        // Wait for the pipes to be created
+        let up = path.with_extension("pipe.up");
+        while !up.exists() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        let client = EchoServiceClient::new(transport::Bincode::new(NamedPipe::connect(&path)?))?;
+        assert_eq!(client.echo("pipe".to_owned())?, "pipe");
+        drop(client);
+
+        server.join().expect("Thread panicked")
+    })?;
+
+    // Pipes are removed by the side which created them
+    assert!(!path.with_extension("pipe.up").exists());
+    assert!(!path.with_extension("pipe.down").exists());
+
+    Ok(())
+}
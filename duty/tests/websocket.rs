@@ -0,0 +1,105 @@
+#![cfg(feature = "websocket")]
+
+use duty::client::Close;
+use duty::error::Error;
+use duty::service;
+use duty::transport::{BincodeCodec, JsonCodec, Transport};
+use duty::websocket::{self, WebSocketTransport};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use tungstenite::Message;
+
+#[service]
+trait DashboardService {
+    fn load(&self, host: String) -> f32;
+    fn hosts(&self) -> Vec<String>;
+}
+
+struct DashboardServiceServer;
+
+impl DashboardService for DashboardServiceServer {
+    fn load(&self, host: String) -> f32 {
+        host.len() as f32 / 4.0
+    }
+
+    fn hosts(&self) -> Vec<String> {
+        vec!["alpha".to_owned(), "beta".to_owned()]
+    }
+}
+
+fn start_server<C: duty::transport::Codec>() -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    std::thread::spawn(move || {
+        websocket::serve::<_, C, _>(&listener, |mut transport| loop {
+            DashboardServiceServer.handle_next_request(&mut transport)?;
+        })
+    });
+
+    Ok(addr)
+}
+
+#[test]
+fn websocket_client() -> Result<(), Error> {
+    let addr = start_server::<BincodeCodec>()?;
+
+    let url = format!("ws://{}/dashboard", addr);
+    let transport =
+        WebSocketTransport::<BincodeCodec, _>::connect(&url, TcpStream::connect(addr)?)?;
+    let client = DashboardServiceClient::new(transport)?;
+
+    assert_eq!(client.hosts()?, vec!["alpha", "beta"]);
+    assert_eq!(client.load("alpha".to_owned())?, 1.25);
+
+    Ok(())
+}
+
+#[test]
+fn websocket_text_messages() -> Result<(), Error> {
+    let addr = start_server::<JsonCodec>()?;
+
+    // Plays the role of a browser client
+    let url = format!("ws://{}/dashboard", addr);
+    let (mut socket, _) = tungstenite::client(url, TcpStream::connect(addr)?).unwrap();
+
+    let request = serde_json::json!({
+        "header": {
+            "request_id": 7,
+            "service": "DashboardService",
+            "method": "load",
+            "trace": {"trace_id": 1, "span_id": 2},
+        },
+        "body": {"load": {"host": "gamma"}},
+    });
+    socket.send(Message::text(request.to_string())).unwrap();
+
+    match socket.read().unwrap() {
+        Message::Text(response) => assert_eq!(response.as_str().trim(), "1.25"),
+        message => panic!("unexpected message {:?}", message),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn websocket_close() -> Result<(), Error> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+
+    let server = std::thread::spawn(move || -> Result<(), Error> {
+        let transport = WebSocketTransport::<BincodeCodec, _>::accept(listener.accept()?.0)?;
+        DashboardServiceServer.serve(transport)
+    });
+
+    let url = format!("ws://{}/dashboard", addr);
+    let transport =
+        WebSocketTransport::<BincodeCodec, _>::connect_stream(&url, TcpStream::connect(addr)?)?;
+    assert_eq!(transport.peer_addr(), Some(addr.to_string().as_str()));
+    let client = DashboardServiceClient::new(transport)?;
+
+    assert_eq!(client.hosts()?, vec!["alpha", "beta"]);
+    Close::close(client)?;
+
+    // Closing handshake ends serving without an error
+    server.join().expect("Thread panicked")
+}
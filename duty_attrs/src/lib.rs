@@ -5,17 +5,23 @@ use quote::{format_ident, quote, ToTokens};
 use std::iter;
 use syn::parse::{Parse, ParseStream};
 use syn::spanned::Spanned;
+use syn::visit_mut::{self, VisitMut};
 use syn::WhereClause;
 use syn::{
-    parse_macro_input, parse_quote, punctuated::Punctuated, token, Expr, ExprPath, FnArg,
-    GenericArgument, GenericParam, Generics, Ident, ItemTrait, Pat, PatType, PathArguments,
-    PathSegment, Receiver, ReturnType, Signature, Token, TraitItem, TraitItemMethod, Type,
-    TypePath, Visibility,
+    parenthesized, parse_macro_input, parse_quote, punctuated::Punctuated, token, Attribute, Expr,
+    ExprPath, FnArg, GenericArgument, GenericParam, Generics, Ident, ItemTrait, Pat, PatType,
+    PathArguments, PathSegment, Receiver, ReturnType, Signature, Token, TraitItem, TraitItemMethod,
+    TraitItemType, Type, TypePath, Visibility,
 };
 
 #[proc_macro_attribute]
-pub fn service(_args: TokenStream, item: TokenStream) -> TokenStream {
-    let mut service = parse_macro_input!(item as Service);
+pub fn service(args: TokenStream, item: TokenStream) -> TokenStream {
+    let attrs = parse_macro_input!(args as ServiceAttrs);
+    let service_trait = parse_macro_input!(item as ItemTrait);
+    let mut service = match Service::new(service_trait, attrs) {
+        Ok(service) => service,
+        Err(error) => return error.to_compile_error().into(),
+    };
     let request = Request::new(&service);
     let client = Client::new(&service, &request);
 
@@ -37,9 +43,88 @@ pub fn service(_args: TokenStream, item: TokenStream) -> TokenStream {
 struct Service {
     service_trait: ItemTrait,
     methods: Vec<RpcMethod>,
+    /// Associated types, which become type parameters of the request and
+    /// the client.
+    assoc_types: Vec<TraitItemType>,
+    attrs: ServiceAttrs,
 }
 
 impl Service {
+    fn new(mut service_trait: ItemTrait, attrs: ServiceAttrs) -> syn::Result<Service> {
+        let mut methods = Vec::new();
+        let mut assoc_types = Vec::new();
+
+        for item in &mut service_trait.items {
+            match item {
+                TraitItem::Method(method) => {
+                    let method_attrs = MethodAttrs::parse(&method.attrs)?;
+
+                    // Our attributes are not known to the compiler
+                    method.attrs.retain(|attr| !is_duty_attr(attr));
+
+                    // Methods with default implementation are local helpers,
+                    // unless marked otherwise
+                    let local = method.default.is_some() && !method_attrs.rpc;
+
+                    if !method_attrs.skip && !local {
+                        methods.push(RpcMethod::new(method, method_attrs)?);
+                    }
+                }
+                TraitItem::Type(assoc_type) => {
+                    if !assoc_type.generics.params.is_empty() {
+                        return Err(syn::Error::new(
+                            assoc_type.generics.span(),
+                            "generic associated types are not supported in service trait",
+                        ));
+                    }
+                    assoc_types.push(assoc_type.clone());
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Service {
+            service_trait,
+            methods,
+            assoc_types,
+            attrs,
+        })
+    }
+
+    fn assoc_idents(&self) -> Vec<Ident> {
+        self.assoc_types
+            .iter()
+            .map(|assoc_type| assoc_type.ident.clone())
+            .collect()
+    }
+
+    /// Type parameters standing for associated types outside of the trait,
+    /// with their bounds if `bounded`.
+    fn assoc_params(&self, bounded: bool) -> impl Iterator<Item = GenericParam> + '_ {
+        let assoc_idents = self.assoc_idents();
+
+        self.assoc_types.iter().map(move |assoc_type| {
+            let ident = &assoc_type.ident;
+            let mut bounds = assoc_type.bounds.clone();
+            for bound in &mut bounds {
+                SelfAssoc {
+                    assoc: &assoc_idents,
+                }
+                .visit_type_param_bound_mut(bound);
+            }
+
+            if bounded && !bounds.is_empty() {
+                parse_quote!(#ident: #bounds)
+            } else {
+                parse_quote!(#ident)
+            }
+        })
+    }
+
+    fn krate(&self) -> &syn::Path {
+        &self.attrs.krate
+    }
+
     fn vis(&self) -> &Visibility {
         &self.service_trait.vis
     }
@@ -57,62 +142,118 @@ impl Service {
     }
 
     fn add_methods(&mut self, request: &Request) {
-        let methods = self.methods().map(|method| method.ident());
-        let args = self.methods().map(|method| {
-            method
-                .rpc_args()
-                .map(|arg| arg.ident.clone())
-                .collect::<Vec<_>>()
-        });
+        let krate = self.krate().clone();
+        let req_enum_path = request.server_path();
 
-        let method_call_args = self.methods().map(RpcMethod::method_call_args);
+        let mut arms = Vec::new();
+        let mut ret_types = Vec::new();
 
-        let req_enum_path = request.path();
-        let req_enum_variants = request.variant_paths();
+        for method in self.methods() {
+            let ident = method.ident();
+            let method_call_args = method.method_call_args();
 
-        let receiver: Receiver = if self.methods().any(RpcMethod::has_ref_mut_self) {
-            parse_quote!(&mut self)
+            for variant in method.variants() {
+                let variant_path = request.server_variant_path(&variant);
+                let args = variant.fields.iter().map(|arg| &arg.ident);
+                let turbofish = variant.turbofish();
+
+                arms.push(quote!(
+                    #variant_path { #( #args ),* } => recorder.respond_owned(transport, Self::#ident #turbofish (#method_call_args)),
+                ));
+                ret_types.push(variant.ret_type);
+            }
+        }
+
+        let has_ref_mut_self = self.methods().any(RpcMethod::has_ref_mut_self);
+
+        let (receiver, serve_receiver): (Receiver, Receiver) = if has_ref_mut_self {
+            (parse_quote!(&mut self), parse_quote!(mut self))
         } else {
-            parse_quote!(&self)
+            (parse_quote!(&self), parse_quote!(self))
         };
 
-        let handle_next_request_method = parse_quote! {
-            /// Waits for the next request and calls appropriate trait method
-            fn handle_next_request<Transport>(#receiver, transport: &mut Transport) -> Result<(), duty::Error>
-            where
-            Transport: duty::Transport,
-            {
-                let request: #req_enum_path = transport.receive()?;
-                match request {
-                    #(
-                        #req_enum_variants { #( #args ),* } => transport.send(&Self::#methods(#method_call_args)),
-                    )*
+        let predicates = quote!(
+            #req_enum_path: Send + 'static,
+            #( #ret_types: Send + 'static, )*
+        );
+
+        let methods: [TraitItemMethod; 4] = [
+            parse_quote! {
+                /// Waits for the next request and calls appropriate trait method
+                fn handle_next_request<Transport>(#receiver, transport: &mut Transport) -> Result<(), #krate::Error>
+                where
+                Transport: #krate::Transport,
+                #predicates
+                {
+                    let start_bytes = transport.byte_count();
+                    let request: #krate::envelope::Envelope<#req_enum_path> = transport.receive_request()?;
+                    let recorder = #krate::metrics::Recorder::start(#krate::metrics::Side::Server, &request.header, start_bytes);
+                    #krate::trace::dispatch(&request.header, || match request.body {
+                        #( #arms )*
+                    })
                 }
-            }
-        };
+            },
+            parse_quote! {
+                /// Handles requests until the client disconnects. Closing the
+                /// connection between requests ends it with `Ok`
+                fn serve<Transport>(#serve_receiver, mut transport: Transport) -> Result<(), #krate::Error>
+                where
+                Self: Sized,
+                Transport: #krate::Transport,
+                #predicates
+                {
+                    loop {
+                        match self.handle_next_request(&mut transport) {
+                            Ok(()) => {}
+                            Err(#krate::Error::ConnectionClosed) => return Ok(()),
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+            },
+            parse_quote! {
+                /// Serves every connection accepted by `listener` in its own
+                /// thread with a clone of the service
+                fn serve_listener<Listener, Transport, MakeTransport>(
+                    self,
+                    listener: &Listener,
+                    make_transport: MakeTransport,
+                ) -> std::io::Result<()>
+                where
+                Self: Sized + Clone + Send + Sync + 'static,
+                Listener: #krate::listener::Listener,
+                Transport: #krate::Transport,
+                MakeTransport: Fn(<Listener as #krate::listener::Listener>::Stream) -> Transport + Send + Sync + 'static,
+                #predicates
+                {
+                    self.serve_with(&#krate::listener::ServerBuilder::new(), listener, make_transport)
+                }
+            },
+            parse_quote! {
+                /// Like `serve_listener`, with connection limit, shutdown, hooks
+                /// and authentication of clients configured by `builder`
+                fn serve_with<Authenticator, Listener, Transport, MakeTransport>(
+                    self,
+                    builder: &#krate::listener::ServerBuilder<Authenticator>,
+                    listener: &Listener,
+                    make_transport: MakeTransport,
+                ) -> std::io::Result<()>
+                where
+                Self: Sized + Clone + Send + Sync + 'static,
+                Authenticator: #krate::auth::Authenticator + Send + Sync + 'static,
+                Listener: #krate::listener::Listener,
+                Transport: #krate::Transport,
+                MakeTransport: Fn(<Listener as #krate::listener::Listener>::Stream) -> Transport + Send + Sync + 'static,
+                #predicates
+                {
+                    builder.serve(listener, make_transport, move |transport| self.clone().serve(transport))
+                }
+            },
+        ];
 
         self.service_trait
             .items
-            .push(TraitItem::Method(handle_next_request_method));
-    }
-}
-
-impl Parse for Service {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        let service_trait: ItemTrait = input.parse()?;
-        let methods = service_trait
-            .items
-            .iter()
-            .filter_map(|item| match item {
-                TraitItem::Method(method) => Some(method.try_into()),
-                _ => None,
-            })
-            .collect::<syn::Result<_>>()?;
-
-        Ok(Service {
-            service_trait,
-            methods,
-        })
+            .extend(methods.into_iter().map(TraitItem::Method));
     }
 }
 
@@ -123,27 +264,53 @@ impl ToTokens for Service {
 }
 
 struct Request {
+    /// Service name sent in the header.
+    service: String,
     path: syn::Path,
     vis: Visibility,
     ident: Ident,
     generics: Generics,
-    variants: Vec<RequestVariant>,
+    /// Associated types of the service, last of the generics.
+    assoc: Vec<Ident>,
+    variants: Vec<MethodVariant>,
+    derives: Vec<syn::Path>,
+    krate: syn::Path,
 }
 
 impl Request {
     fn new(service: &Service) -> Request {
-        let ident = format_ident!("{}Request", service.ident());
+        let ident = service
+            .attrs
+            .request
+            .clone()
+            .unwrap_or_else(|| format_ident!("{}Request", service.ident()));
+
+        let mut generics = service.generics().clone();
+        generics.params.extend(service.assoc_params(false));
+        if generics.lt_token.is_none() && !generics.params.is_empty() {
+            generics.lt_token = Some(Default::default());
+            generics.gt_token = Some(Default::default());
+        }
 
-        let path = ident_to_path(&ident, Some(service.generics()));
+        let path = ident_to_path(&ident, Some(&generics));
 
-        let variants = service.methods().map(RequestVariant::new).collect();
+        let assoc = service.assoc_idents();
+        let variants = service
+            .methods()
+            .flat_map(RpcMethod::variants)
+            .map(|variant| variant.without_self(&assoc))
+            .collect();
 
         Request {
+            service: service.ident().to_string(),
             path,
             vis: service.vis().clone(),
             ident,
-            generics: service.generics().clone(),
+            generics,
+            assoc,
             variants,
+            derives: service.attrs.derives.clone(),
+            krate: service.krate().clone(),
         }
     }
 
@@ -151,10 +318,43 @@ impl Request {
         &self.path
     }
 
-    fn variant_paths<'a>(&'a self) -> impl Iterator<Item = syn::Path> + 'a {
+    fn variant_path(&self, variant: &MethodVariant) -> syn::Path {
+        enum_variant_to_path(&self.ident, &self.generics, &variant.ident)
+    }
+
+    /// Path of the request inside of the service trait, where associated
+    /// types are referred to through `Self`.
+    fn server_path(&self) -> syn::Path {
+        self.with_self(self.path.clone())
+    }
+
+    fn server_variant_path(&self, variant: &MethodVariant) -> syn::Path {
+        self.with_self(self.variant_path(variant))
+    }
+
+    fn with_self(&self, mut path: syn::Path) -> syn::Path {
+        let types: Vec<Type> = self
+            .assoc
+            .iter()
+            .map(|ident| parse_quote!(Self::#ident))
+            .collect();
+
+        Substitute {
+            params: &self.assoc,
+            types: &types,
+        }
+        .visit_path_mut(&mut path);
+
+        path
+    }
+
+    /// Position of `variant` in the enum, which non self-describing formats
+    /// encode instead of its name.
+    fn variant_index(&self, variant: &MethodVariant) -> u32 {
         self.variants
             .iter()
-            .map(|variant| enum_variant_to_path(&self.ident, &self.generics, &variant.ident))
+            .position(|v| v.ident == variant.ident)
+            .expect("variant of the request") as u32
     }
 }
 
@@ -163,44 +363,90 @@ impl ToTokens for Request {
         let vis = &self.vis;
         let ident = &self.ident;
         let variants = &self.variants;
+        let derives = &self.derives;
+        let krate = &self.krate;
+        let serde_crate = format!("{}::private::serde", path_to_string(krate));
+
+        let service = &self.service;
+        let variant_names = variants.iter().map(|variant| &variant.name);
+        let variant_idents = variants.iter().map(|variant| &variant.ident);
+        let variant_methods = variants.iter().map(|variant| &variant.method);
+        let mut methods: Vec<_> = variants.iter().map(|variant| &variant.method).collect();
+        methods.dedup();
 
         let (impl_generics, ty_generics, where_clause) = self.generics.split_for_impl();
 
         output.extend(quote!(
-            #[derive(serde::Serialize, serde::Deserialize)]
+            #[derive(#krate::private::serde::Serialize, #krate::private::serde::Deserialize #(, #derives)*)]
+            #[serde(crate = #serde_crate)]
             #vis enum #ident #ty_generics {
                 #(
                     #variants,
                 )*
             }
 
-            impl #impl_generics #ident #ty_generics #where_clause {
+            impl #impl_generics #krate::envelope::ServiceRequest for #ident #ty_generics #where_clause {
+                const SERVICE: &'static str = #service;
+                const METHODS: &'static [&'static str] = &[#( #methods ),*];
+                const VARIANTS: &'static [&'static str] = &[#( #variant_names ),*];
+
+                fn method(&self) -> &'static str {
+                    match *self {
+                        #( Self::#variant_idents { .. } => #variant_methods, )*
+                    }
+                }
             }
         ));
     }
 }
 
-struct RequestVariant {
+/// Request variant of a method. Generic methods have one for each of their
+/// instances.
+#[derive(Clone)]
+struct MethodVariant {
     ident: Ident,
+    /// Name seen by self-describing formats.
+    name: String,
+    /// Method name sent in the header, shared by all instances.
+    method: String,
     fields: Vec<RpcArg>,
+    ret_type: Type,
+    /// Types substituted for type parameters of the method.
+    types: Vec<Type>,
 }
 
-impl RequestVariant {
-    fn new(method: &RpcMethod) -> RequestVariant {
-        RequestVariant {
-            ident: format_ident!("{}", method.ident().to_string().to_class_case()),
-            fields: method.rpc_args().cloned().collect(),
+impl MethodVariant {
+    /// Refers to associated types `Self::T` as type parameters `T`.
+    fn without_self(mut self, assoc: &[Ident]) -> MethodVariant {
+        let mut without_self = SelfAssoc { assoc };
+        for field in &mut self.fields {
+            without_self.visit_type_mut(&mut field.arg_type);
+        }
+        without_self.visit_type_mut(&mut self.ret_type);
+        self
+    }
+
+    fn turbofish(&self) -> TokenStream2 {
+        let types = &self.types;
+        if types.is_empty() {
+            quote!()
+        } else {
+            quote!(::<#( #types ),*>)
         }
     }
 }
 
-impl ToTokens for RequestVariant {
+impl ToTokens for MethodVariant {
     fn to_tokens(&self, output: &mut TokenStream2) {
         let ident = &self.ident;
+        let method = &self.name;
         let field_idents = self.fields.iter().map(|field| &field.ident);
         let field_types = self.fields.iter().map(|field| &field.arg_type);
 
+        // Self-describing formats see the variant under the name of the
+        // trait method, as language-neutral clients call it
         output.extend(quote!(
+            #[serde(rename = #method)]
             #ident { #( #field_idents: #field_types, )* }
         ))
     }
@@ -211,35 +457,61 @@ struct Client {
     methods: Vec<ClientMethod>,
     vis: Visibility,
     generics: Generics,
+    krate: syn::Path,
 }
 
 impl Client {
     fn new(service: &Service, request: &Request) -> Client {
-        let ident = format_ident!("{}Client", service.ident());
+        let ident = service
+            .attrs
+            .client
+            .clone()
+            .unwrap_or_else(|| format_ident!("{}Client", service.ident()));
         let vis = service.vis().clone();
+        let assoc = service.assoc_idents();
 
         let methods = service
             .methods()
-            .zip(request.variant_paths())
-            .map(|(method, variant_path)| {
-                let req_fields = method.rpc_args().map(|arg| arg.ident.clone()).collect();
-
-                ClientMethod {
-                    vis: vis.clone(),
-                    sig: method.sig.clone(),
-                    req_variant: variant_path,
-                    req_fields,
-                }
+            .map(|method| ClientMethod {
+                vis: vis.clone(),
+                service: service.ident().to_string(),
+                name: method.name(),
+                idempotent: method.idempotent,
+                instance_trait: format_ident!(
+                    "{}{}Instance",
+                    ident,
+                    method.ident().to_string().to_class_case()
+                ),
+                sig: {
+                    let mut sig = method.sig.clone();
+                    SelfAssoc { assoc: &assoc }.visit_signature_mut(&mut sig);
+                    sig
+                },
+                krate: service.krate().clone(),
+                req_path: request.path().clone(),
+                req_name: request.ident.to_string(),
+                variants: method
+                    .variants()
+                    .into_iter()
+                    .map(|variant| variant.without_self(&assoc))
+                    .map(|variant| ClientVariant {
+                        path: request.variant_path(&variant),
+                        index: request.variant_index(&variant),
+                        variant,
+                    })
+                    .collect(),
             })
             .collect();
 
-        let generics = service.generics().clone();
+        let mut generics = service.generics().clone();
+        generics.params.extend(service.assoc_params(true));
 
         Client {
             ident,
             methods,
             vis,
             generics,
+            krate: service.krate().clone(),
         }
     }
 }
@@ -249,6 +521,8 @@ impl ToTokens for Client {
         let ident = &self.ident;
         let vis = &self.vis;
         let methods = &self.methods;
+        let krate = &self.krate;
+        let instance_traits = self.methods.iter().filter_map(ClientMethod::instance_trait);
 
         let mut generics = Generics {
             lt_token: Some(Default::default()),
@@ -256,7 +530,7 @@ impl ToTokens for Client {
             gt_token: Some(Default::default()),
             where_clause: Some(WhereClause {
                 where_token: Default::default(),
-                predicates: parse_quote!(Transport: duty::Transport,),
+                predicates: parse_quote!(Transport: #krate::Transport,),
             }),
         };
 
@@ -277,36 +551,210 @@ impl ToTokens for Client {
         let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
         output.extend(quote!(
+            #( #instance_traits )*
+
             #vis struct #ident #ty_generics {
                 transport: std::cell::RefCell<Transport>,
+                next_request_id: std::cell::Cell<u64>,
                 phantom: std::marker::PhantomData<(#gen_args)>,
             }
 
             impl #impl_generics #ident #ty_generics #where_clause {
-                #vis fn new(transport: Transport) -> std::result::Result<Self, duty::Error> {
+                #vis fn new(transport: Transport) -> std::result::Result<Self, #krate::Error> {
                     Ok(Self {
                         transport: std::cell::RefCell::new(transport),
+                        next_request_id: std::cell::Cell::new(0),
                         phantom: std::marker::PhantomData {}
                     })
                 }
 
+                /// Authenticates with `authenticator` before any call is made
+                #vis fn with_auth<Authenticator>(mut transport: Transport, authenticator: &Authenticator) -> std::result::Result<Self, #krate::Error>
+                where
+                    Authenticator: #krate::auth::Authenticator,
+                {
+                    authenticator.authenticate(&mut transport)?;
+                    Self::new(transport)
+                }
+
                 #(
                     #methods
                 )*
             }
+
+            impl #impl_generics #krate::client::Close for #ident #ty_generics #where_clause {
+                fn close(client: Self) -> std::result::Result<(), #krate::Error> {
+                    #krate::Transport::close(&mut client.transport.into_inner())
+                }
+            }
         ));
     }
 }
 
 struct ClientMethod {
     vis: Visibility,
+    service: String,
+    /// Method name sent in the header.
+    name: String,
+    /// Header marks calls as safe to repeat.
+    idempotent: bool,
+    /// Trait implemented for type parameters of every instance of generic
+    /// method, so that calls with other types don't compile.
+    instance_trait: Ident,
     sig: Signature,
-    req_variant: syn::Path,
-    req_fields: Vec<Ident>,
+    krate: syn::Path,
+    req_path: syn::Path,
+    req_name: String,
+    variants: Vec<ClientVariant>,
+}
+
+struct ClientVariant {
+    path: syn::Path,
+    index: u32,
+    variant: MethodVariant,
+}
+
+impl ClientMethod {
+    /// Sends request of `variant` with fields taken from local variables of
+    /// the same name, evaluates to the result of the call.
+    fn call(&self, client_variant: &ClientVariant) -> TokenStream2 {
+        let krate = &self.krate;
+        let service = &self.service;
+        let method = &self.name;
+        let variant = &client_variant.variant;
+        let variant_path = &client_variant.path;
+        let req_fields = variant.fields.iter().map(|field| &field.ident);
+
+        let call = if variant.fields.iter().any(|field| field.borrowed) {
+            let request = self.borrowed_request(client_variant);
+            quote!(transport.call(&#krate::envelope::Envelope::new(header, #request)))
+        } else {
+            quote!(transport.call_owned(#krate::envelope::Envelope::new(header, #variant_path {#( #req_fields, )*})))
+        };
+
+        let idempotent = self.idempotent.then(|| quote!(.idempotent()));
+
+        quote!({
+            let request_id = self.next_request_id.get();
+            self.next_request_id.set(request_id.wrapping_add(1));
+
+            let header = #krate::envelope::Header::new(request_id, #service, #method)#idempotent;
+            let span = #krate::trace::client_span(&header);
+            let _span_guard = span.enter();
+
+            let mut transport = self.transport.borrow_mut();
+            let recorder = #krate::metrics::Recorder::start(#krate::metrics::Side::Client, &header, transport.byte_count());
+            let result = #call;
+            recorder.finish(&*transport, &result);
+            result.map_err(|e| e.with_context(|| #krate::error::CallContext::new(#service, #method, transport.peer_addr())))
+        })
+    }
+
+    /// Request serialized just like `variant` of the request enum, but
+    /// referencing arguments instead of owning them, so that borrowed
+    /// arguments don't need to be copied.
+    fn borrowed_request(&self, client_variant: &ClientVariant) -> TokenStream2 {
+        let krate = &self.krate;
+        let serde_crate = format!("{}::private::serde", path_to_string(krate));
+        let req_name = &self.req_name;
+        let variant = &client_variant.variant;
+        let name = &variant.name;
+        let fields: Vec<_> = variant.fields.iter().map(|field| &field.ident).collect();
+        let type_params: Vec<_> = (0..fields.len()).map(|i| format_ident!("A{}", i)).collect();
+        // Variants before this one only keep its index the same as in the
+        // request enum, which non self-describing formats encode
+        let skipped = (0..client_variant.index).map(|i| format_ident!("Skipped{}", i));
+        let values = variant.fields.iter().map(|field| {
+            let ident = &field.ident;
+            if field.borrowed {
+                quote!(#ident)
+            } else {
+                quote!(&#ident)
+            }
+        });
+
+        quote!({
+            #[derive(#krate::private::serde::Serialize)]
+            #[serde(crate = #serde_crate, rename = #req_name)]
+            #[allow(dead_code)]
+            enum BorrowedRequest<#( #type_params ),*> {
+                #(
+                    #[serde(skip)]
+                    #skipped,
+                )*
+                #[serde(rename = #name)]
+                Request { #( #fields: #type_params ),* },
+            }
+
+            BorrowedRequest::Request { #( #fields: #values ),* }
+        })
+    }
+
+    /// Trait implemented for tuples of types of each instance, if the
+    /// method is generic.
+    fn instance_trait(&self) -> Option<TokenStream2> {
+        self.sig.generics.type_params().next()?;
+
+        let vis = &self.vis;
+        let instance_trait = &self.instance_trait;
+        let doc = format!(
+            "Type parameters `{}` can be called with, one tuple per instance",
+            self.sig.ident
+        );
+        let types = self.variants.iter().map(|v| &v.variant.types);
+
+        Some(quote!(
+            #[doc = #doc]
+            #[doc(hidden)]
+            #vis trait #instance_trait {}
+
+            #( impl #instance_trait for (#( #types, )*) {} )*
+        ))
+    }
+
+    /// Picks the instance of generic method by comparing its type
+    /// parameters with types of each instance. Values are moved between the
+    /// generic and concrete types, which are the same when they match.
+    fn generic_body(&self) -> TokenStream2 {
+        let krate = &self.krate;
+        let type_params: Vec<_> = self.sig.generics.type_params().map(|p| &p.ident).collect();
+        let arg_idents: Vec<_> = self.variants[0]
+            .variant
+            .fields
+            .iter()
+            .map(|f| &f.ident)
+            .collect();
+        let method = self.sig.ident.to_string();
+
+        let branches = self.variants.iter().map(|client_variant| {
+            let variant = &client_variant.variant;
+            let types = &variant.types;
+            let field_types = variant.fields.iter().map(|f| &f.arg_type);
+            let ret_type = &variant.ret_type;
+            let call = self.call(client_variant);
+
+            quote!(
+                if #( std::any::TypeId::of::<#type_params>() == std::any::TypeId::of::<#types>() )&&* {
+                    #( let #arg_idents: #field_types = #krate::private::cast(#arg_idents); )*
+                    let result: Result<#ret_type, #krate::Error> = #call;
+                    return result.map(#krate::private::cast);
+                }
+            )
+        });
+
+        quote!({
+            #( #branches )*
+
+            // Ruled out by the bound on the instance trait
+            let types: &[&str] = &[#( std::any::type_name::<#type_params>() ),*];
+            unreachable!("{} has no instance for <{}>", #method, types.join(", "))
+        })
+    }
 }
 
 impl ToTokens for ClientMethod {
     fn to_tokens(&self, output: &mut TokenStream2) {
+        let krate = &self.krate;
         let vis = &self.vis;
         let ident = &self.sig.ident;
         let args = self
@@ -314,22 +762,39 @@ impl ToTokens for ClientMethod {
             .inputs
             .iter()
             .filter(|arg| matches!(arg, FnArg::Typed(_)));
-        let req_variant = &self.req_variant;
-        let req_fields = &self.req_fields;
+        let req_path = &self.req_path;
 
         let unit_type = Box::new(parse_quote!(()));
 
         let ret_type = match &self.sig.output {
             ReturnType::Default => &unit_type,
-            ReturnType::Type(_, t) => &t,
+            ReturnType::Type(_, t) => t,
+        };
+
+        let (impl_generics, _, where_clause) = self.sig.generics.split_for_impl();
+        let mut predicates: Vec<TokenStream2> = where_clause
+            .iter()
+            .flat_map(|where_clause| where_clause.predicates.iter())
+            .map(ToTokens::to_token_stream)
+            .collect();
+        predicates.push(quote!(#req_path: Send + 'static));
+
+        let body = if self.sig.generics.params.is_empty() {
+            predicates.push(quote!(#ret_type: Send + 'static));
+            self.call(&self.variants[0])
+        } else {
+            let type_params: Vec<_> = self.sig.generics.type_params().map(|p| &p.ident).collect();
+            let instance_trait = &self.instance_trait;
+            predicates.extend(type_params.iter().map(|ident| quote!(#ident: 'static)));
+            predicates.push(quote!((#( #type_params, )*): #instance_trait));
+            self.generic_body()
         };
 
         output.extend(quote!(
-            #vis fn #ident (&self #(, #args)* ) -> Result<#ret_type, duty::Error> {
-                self.transport
-                    .borrow_mut()
-                    .send_receive(& #req_variant {#( #req_fields, )*})
-            }
+            #vis fn #ident #impl_generics (&self #(, #args)* ) -> Result<#ret_type, #krate::Error>
+            where
+                #( #predicates, )*
+            #body
         ));
     }
 }
@@ -338,6 +803,10 @@ struct RpcMethod {
     sig: Signature,
     rpc_args: Vec<RpcArg>,
     method_call_args: Punctuated<Expr, token::Comma>,
+    /// Types to instantiate generic method with, one list per instance.
+    instances: Vec<Vec<Type>>,
+    rename: Option<String>,
+    idempotent: bool,
 }
 
 impl RpcMethod {
@@ -345,8 +814,74 @@ impl RpcMethod {
         &self.sig.ident
     }
 
-    fn rpc_args(&self) -> impl Iterator<Item = &RpcArg> {
-        self.rpc_args.iter()
+    /// Name of the method on the wire.
+    fn name(&self) -> String {
+        self.rename
+            .clone()
+            .unwrap_or_else(|| self.ident().to_string())
+    }
+
+    fn ret_type(&self) -> Type {
+        match &self.sig.output {
+            ReturnType::Default => parse_quote!(()),
+            ReturnType::Type(_, t) => t.as_ref().clone(),
+        }
+    }
+
+    fn variants(&self) -> Vec<MethodVariant> {
+        let ident = self.ident();
+        let class_name = ident.to_string().to_class_case();
+
+        if self.instances.is_empty() {
+            return vec![MethodVariant {
+                ident: format_ident!("{}", class_name),
+                name: self.name(),
+                method: self.name(),
+                fields: self.rpc_args.clone(),
+                ret_type: self.ret_type(),
+                types: Vec::new(),
+            }];
+        }
+
+        let params: Vec<_> = self
+            .sig
+            .generics
+            .type_params()
+            .map(|param| param.ident.clone())
+            .collect();
+
+        self.instances
+            .iter()
+            .enumerate()
+            .map(|(index, types)| {
+                let mut substitute = Substitute {
+                    params: &params,
+                    types,
+                };
+
+                let mut fields = self.rpc_args.clone();
+                for field in &mut fields {
+                    substitute.visit_type_mut(&mut field.arg_type);
+                }
+
+                let mut ret_type = self.ret_type();
+                substitute.visit_type_mut(&mut ret_type);
+
+                let type_names: Vec<_> = types
+                    .iter()
+                    .map(|ty| ty.to_token_stream().to_string().replace(' ', ""))
+                    .collect();
+
+                MethodVariant {
+                    ident: format_ident!("{}Instance{}", class_name, index),
+                    name: format!("{}<{}>", self.name(), type_names.join(",")),
+                    method: self.name(),
+                    fields,
+                    ret_type,
+                    types: types.clone(),
+                }
+            })
+            .collect()
     }
 
     fn method_call_args(&self) -> &Punctuated<Expr, token::Comma> {
@@ -365,63 +900,95 @@ impl RpcMethod {
     }
 }
 
-impl TryFrom<&TraitItemMethod> for RpcMethod {
-    type Error = syn::Error;
+impl RpcMethod {
+    fn new(method: &TraitItemMethod, attrs: MethodAttrs) -> syn::Result<RpcMethod> {
+        let generics = &method.sig.generics;
 
-    fn try_from(method: &TraitItemMethod) -> Result<Self, Self::Error> {
-        if let Some(lt_token) = &method.sig.generics.lt_token {
-            let span = method
-                .sig
-                .generics
-                .gt_token
-                .as_ref()
-                .and_then(|gt| lt_token.span.join(gt.span))
-                .unwrap_or_else(|| lt_token.span);
+        if let Some(param) = generics
+            .params
+            .iter()
+            .find(|param| !matches!(param, GenericParam::Type(_)))
+        {
+            return Err(syn::Error::new(
+                param.span(),
+                "only type parameters are supported in generic service methods",
+            ));
+        }
 
+        let type_param_count = generics.type_params().count();
+        if type_param_count > 0 && attrs.instances.is_empty() {
+            return Err(syn::Error::new(
+                generics.span(),
+                "generic service methods need #[duty(instance(...))] attribute for every set of types they are called with",
+            ));
+        }
+
+        if let Some(instance) = attrs
+            .instances
+            .iter()
+            .find(|instance| instance.len() != type_param_count)
+        {
+            let span = instance
+                .first()
+                .map_or_else(|| generics.span(), Spanned::span);
             return Err(syn::Error::new(
                 span,
-                "generic methods are not supported in service trait",
+                format!("expected {} types in instance", type_param_count),
             ));
         }
 
-        let rpc_args = method
-            .sig
+        let mut sig = method.sig.clone();
+        let mut rpc_args = Vec::new();
+
+        // Arguments bound by patterns get synthetic names, which the
+        // generated code uses in place of the pattern
+        for (index, pat_type) in sig
             .inputs
-            .iter()
+            .iter_mut()
             .filter_map(|arg| match arg {
-                FnArg::Typed(pat_type) => Some(pat_type.try_into()),
+                FnArg::Typed(pat_type) => Some(pat_type),
                 _ => None,
             })
-            .collect::<syn::Result<_>>()?;
+            .enumerate()
+        {
+            let arg = RpcArg::new(pat_type, index)?;
+            let ident = &arg.ident;
+            *pat_type.pat = parse_quote!(#ident);
+            rpc_args.push(arg);
+        }
 
-        let method_call_args = method
-            .sig
-            .inputs
-            .iter()
-            .map(|arg| match arg {
-                FnArg::Receiver(_) => Ok(Expr::Path(ExprPath {
-                    attrs: Vec::new(),
-                    qself: None,
-                    path: ident_to_path(&format_ident!("self"), None),
-                })),
-                FnArg::Typed(pat_type) => match pat_type.pat.as_ref() {
-                    Pat::Ident(pat_ident) => Ok(Expr::Path(ExprPath {
-                        attrs: Vec::new(),
-                        qself: None,
-                        path: ident_to_path(&pat_ident.ident, None),
-                    })),
-                    _ => Err(syn::Error::new(
-                        pat_type.span(),
-                        format!("only basic patterns are supported in service trait methods"),
-                    )),
-                },
-            })
-            .collect::<syn::Result<_>>()?;
+        if type_param_count > 0 {
+            if let Some(arg) = rpc_args.iter().find(|arg| arg.borrowed) {
+                return Err(syn::Error::new(
+                    arg.ident.span(),
+                    "borrowed arguments are not supported in generic service methods",
+                ));
+            }
+        }
+
+        let method_call_args = sig
+            .receiver()
+            .map(|_| -> Expr { parse_quote!(self) })
+            .into_iter()
+            .chain(rpc_args.iter().map(|arg| -> Expr {
+                let ident = &arg.ident;
+
+                // Borrowed arguments are received owned
+                if arg.borrowed {
+                    parse_quote!(&#ident)
+                } else {
+                    parse_quote!(#ident)
+                }
+            }))
+            .collect();
 
         Ok(RpcMethod {
-            sig: method.sig.clone(),
+            sig,
             rpc_args,
             method_call_args,
+            instances: attrs.instances,
+            rename: attrs.rename,
+            idempotent: attrs.idempotent,
         })
     }
 }
@@ -429,23 +996,230 @@ impl TryFrom<&TraitItemMethod> for RpcMethod {
 #[derive(Clone)]
 struct RpcArg {
     ident: Ident,
+    /// Type of the request field, owned version of the argument type.
     arg_type: Box<Type>,
+    /// Argument is a reference, which the service receives owned.
+    borrowed: bool,
 }
 
-impl TryFrom<&PatType> for RpcArg {
-    type Error = syn::Error;
+impl RpcArg {
+    /// Argument of the method at `index`, not counting the receiver.
+    fn new(arg_type: &PatType, index: usize) -> syn::Result<RpcArg> {
+        let ident = match arg_type.pat.as_ref() {
+            Pat::Ident(pat_ident) if pat_ident.subpat.is_none() && pat_ident.by_ref.is_none() => {
+                pat_ident.ident.to_owned()
+            }
+            // Prefix is reserved, so the name doesn't collide with other
+            // arguments
+            _ => format_ident!("__duty_arg{}", index),
+        };
 
-    fn try_from(arg_type: &PatType) -> Result<Self, Self::Error> {
-        match arg_type.pat.as_ref() {
-            Pat::Ident(pat_ident) => Ok(RpcArg {
-                ident: pat_ident.ident.to_owned(),
-                arg_type: arg_type.ty.clone(),
-            }),
-            _ => Err(syn::Error::new(
-                arg_type.span(),
-                format!("only basic patterns are supported in service trait methods"),
-            )),
+        Ok(RpcArg {
+            ident,
+            arg_type: Box::new(owned_type(&arg_type.ty)?),
+            borrowed: matches!(arg_type.ty.as_ref(), Type::Reference(_)),
+        })
+    }
+}
+
+/// Maps borrowed argument type to the type it is sent as: `&[T]` to
+/// `Vec<T>`, `&str` to `String` and `&T` to `T`.
+fn owned_type(ty: &Type) -> syn::Result<Type> {
+    let reference = match ty {
+        Type::Reference(reference) => reference,
+        _ => return Ok(ty.clone()),
+    };
+
+    if let Some(mutability) = &reference.mutability {
+        return Err(syn::Error::new(
+            mutability.span(),
+            "mutable references are not supported in service trait methods",
+        ));
+    }
+
+    Ok(match reference.elem.as_ref() {
+        Type::Slice(slice) => {
+            let elem = &slice.elem;
+            parse_quote!(std::vec::Vec<#elem>)
+        }
+        Type::Path(TypePath { qself: None, path }) if path.is_ident("str") => {
+            parse_quote!(std::string::String)
         }
+        elem => elem.clone(),
+    })
+}
+
+/// Arguments of the `service` attribute.
+struct ServiceAttrs {
+    client: Option<Ident>,
+    request: Option<Ident>,
+    /// Additional derives of the request enum.
+    derives: Vec<syn::Path>,
+    /// Path of the duty crate, for crates re-exporting it.
+    krate: syn::Path,
+}
+
+impl Parse for ServiceAttrs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut attrs = ServiceAttrs {
+            client: None,
+            request: None,
+            derives: Vec::new(),
+            krate: parse_quote!(duty),
+        };
+
+        while !input.is_empty() {
+            if input.peek(Token![crate]) {
+                input.parse::<Token![crate]>()?;
+                input.parse::<Token![=]>()?;
+                attrs.krate = input.parse::<syn::LitStr>()?.parse()?;
+            } else {
+                let key: Ident = input.parse()?;
+                match key.to_string().as_str() {
+                    "client" => {
+                        input.parse::<Token![=]>()?;
+                        attrs.client = Some(input.parse()?);
+                    }
+                    "request" => {
+                        input.parse::<Token![=]>()?;
+                        attrs.request = Some(input.parse()?);
+                    }
+                    "derive" => {
+                        let content;
+                        parenthesized!(content in input);
+                        let derives =
+                            Punctuated::<syn::Path, Token![,]>::parse_terminated(&content)?;
+                        attrs.derives.extend(derives);
+                    }
+                    _ => {
+                        return Err(syn::Error::new(
+                            key.span(),
+                            format!("unknown service attribute `{}`", key),
+                        ))
+                    }
+                }
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        Ok(attrs)
+    }
+}
+
+/// `#[duty(...)]` attributes of a trait method.
+#[derive(Default)]
+struct MethodAttrs {
+    instances: Vec<Vec<Type>>,
+    /// Method is not exposed to clients.
+    skip: bool,
+    /// Method with default implementation is exposed to clients.
+    rpc: bool,
+    /// Name of the method on the wire.
+    rename: Option<String>,
+    /// Calls may be repeated without changing the outcome.
+    idempotent: bool,
+}
+
+impl MethodAttrs {
+    fn parse(attrs: &[Attribute]) -> syn::Result<MethodAttrs> {
+        let mut method_attrs = MethodAttrs::default();
+
+        for attr in attrs.iter().filter(|attr| is_duty_attr(attr)) {
+            attr.parse_args_with(|input: ParseStream| {
+                while !input.is_empty() {
+                    let key: Ident = input.parse()?;
+                    match key.to_string().as_str() {
+                        "instance" => {
+                            let content;
+                            parenthesized!(content in input);
+                            let types = Punctuated::<Type, Token![,]>::parse_terminated(&content)?;
+                            method_attrs.instances.push(types.into_iter().collect());
+                        }
+                        "skip" => method_attrs.skip = true,
+                        "rpc" => method_attrs.rpc = true,
+                        "idempotent" => method_attrs.idempotent = true,
+                        "rename" => {
+                            input.parse::<Token![=]>()?;
+                            method_attrs.rename = Some(input.parse::<syn::LitStr>()?.value());
+                        }
+                        _ => {
+                            return Err(syn::Error::new(
+                                key.span(),
+                                format!("unknown duty attribute `{}`", key),
+                            ))
+                        }
+                    }
+
+                    if !input.is_empty() {
+                        input.parse::<Token![,]>()?;
+                    }
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(method_attrs)
+    }
+}
+
+fn path_to_string(path: &syn::Path) -> String {
+    path.to_token_stream().to_string().replace(' ', "")
+}
+
+fn is_duty_attr(attr: &Attribute) -> bool {
+    attr.path.is_ident("duty")
+}
+
+/// Replaces type parameters with concrete types.
+struct Substitute<'a> {
+    params: &'a [Ident],
+    types: &'a [Type],
+}
+
+impl VisitMut for Substitute<'_> {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        if let Type::Path(TypePath { qself: None, path }) = ty {
+            let index = path
+                .get_ident()
+                .and_then(|ident| self.params.iter().position(|param| param == ident));
+
+            if let Some(index) = index {
+                *ty = self.types[index].clone();
+                return;
+            }
+        }
+
+        visit_mut::visit_type_mut(self, ty);
+    }
+}
+
+/// Replaces associated types `Self::T` with type parameters `T`.
+struct SelfAssoc<'a> {
+    assoc: &'a [Ident],
+}
+
+impl VisitMut for SelfAssoc<'_> {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        if let Type::Path(TypePath { qself: None, path }) = ty {
+            let segments: Vec<_> = path.segments.iter().collect();
+
+            if let [self_segment, assoc_segment] = segments.as_slice() {
+                if self_segment.ident == "Self"
+                    && self_segment.arguments.is_empty()
+                    && assoc_segment.arguments.is_empty()
+                    && self.assoc.contains(&assoc_segment.ident)
+                {
+                    let ident = assoc_segment.ident.clone();
+                    *ty = parse_quote!(#ident);
+                    return;
+                }
+            }
+        }
+
+        visit_mut::visit_type_mut(self, ty);
     }
 }
 
@@ -458,7 +1232,7 @@ fn enum_variant_to_path(
 
     segments.push(PathSegment {
         ident: enum_ident.clone(),
-        arguments: generics_to_path_args(Some(&generics), true),
+        arguments: generics_to_path_args(Some(generics), true),
     });
 
     segments.push(PathSegment {